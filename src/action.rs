@@ -1,26 +1,32 @@
 use std::{
-  any::Any,
+  error::Error,
   sync::{Arc, RwLock, Weak},
+  time::Duration,
 };
 
+use actix_rt::time::sleep;
+use serde_json::json;
 use uuid::Uuid;
-use webthing::{Action, BaseAction, Thing};
+use webthing::{Action, BaseAction, BaseEvent, Thing, server::ActionGenerator};
 
-use door_server::{Door, GarageDoor};
+use door_server::{Actuator, Capability, close_and_wait};
+
+use crate::{hold_open::HoldOpenHandle, intrusion::IntrusionHandle};
+
+pub type SharedDoor = Arc<tokio::sync::RwLock<Box<dyn Actuator>>>;
+
+type Input = serde_json::Map<String, serde_json::Value>;
 
 macro_rules! action {
-  ($ty:ident, $action_name:expr, $method:expr) => {
+  ($ty:ident, $action_name:expr, $target:ty, $method:expr) => {
     pub struct $ty {
       action: BaseAction,
-      door: Arc<tokio::sync::RwLock<Box<dyn Any + Send + Sync>>>,
+      target: $target,
     }
 
     impl $ty {
-      pub fn new(
-        thing: Weak<RwLock<Box<dyn Thing>>>,
-        door: Arc<tokio::sync::RwLock<Box<dyn Any + Send + Sync>>>,
-      ) -> Self {
-        Self { action: BaseAction::new(Uuid::new_v4().to_string(), $action_name.to_owned(), None, thing), door }
+      pub fn new(thing: Weak<RwLock<Box<dyn Thing>>>, target: $target, input: Option<Input>) -> Self {
+        Self { action: BaseAction::new(Uuid::new_v4().to_string(), $action_name.to_owned(), input, thing), target }
       }
     }
 
@@ -73,17 +79,29 @@ macro_rules! action {
         let thing = if let Some(thing) = self.get_thing() { thing.clone() } else { return };
         let action_name = self.get_name();
         let id = self.get_id();
-        let door = self.door.clone();
-
-        actix_rt::spawn(async move {
-          let mut door = door.write().await;
+        let target = self.target.clone();
+        let input = self.get_input().unwrap_or_default();
+        let actor = crate::audit::actor();
 
+        actix_rt::spawn(crate::audit::scope(actor, async move {
           #[allow(clippy::redundant_closure_call)]
-          $method(&mut *door).await;
+          let result = $method(target, &thing, input).await;
 
           let mut thing = thing.write().unwrap();
-          thing.finish_action(action_name, id);
-        });
+          match result {
+            Ok(()) => thing.finish_action(action_name, id),
+            Err(err) => {
+              log::error!("Action {action_name} of {} failed: {err}", thing.get_title());
+
+              if let Some(action) = thing.get_action(action_name, id) {
+                let mut action = action.write().unwrap();
+                action.finish();
+                action.set_status("error".to_owned());
+                thing.action_notify(action.as_action_description());
+              }
+            },
+          }
+        }));
       }
 
       fn cancel(&mut self) {
@@ -97,22 +115,104 @@ macro_rules! action {
   };
 }
 
-async fn unlock_door(door: &mut Box<dyn Any + Send + Sync>) {
-  if let Some(ref mut door) = door.downcast_mut::<Door>() {
-    door.open().await;
-  } else if let Some(ref mut door) = door.downcast_mut::<GarageDoor>() {
-    door.open().await;
-  } else {
-    unreachable!()
-  }
+type ActionResult = Result<(), Box<dyn Error>>;
+
+/// Whether a door with the given capabilities supports the given action.
+pub fn is_supported(action_name: &str, capabilities: &[Capability]) -> bool {
+  let required: &[Capability] = match action_name {
+    "unlock" => &[Capability::Open],
+    "lock" => &[Capability::Close],
+    "stop" => &[Capability::Stop],
+    "toggle" => &[Capability::Toggle],
+    "hold_open" | "release" => &[Capability::Hold],
+    _ => return false,
+  };
+
+  required.iter().all(|capability| capabilities.contains(capability))
+}
+
+/// Request an action like `POST /things/{id}/actions` does, validating its input.
+pub fn request(
+  generator: &dyn ActionGenerator,
+  thing: &Arc<RwLock<Box<dyn Thing>>>,
+  name: &str,
+  input: Option<&serde_json::Value>,
+) -> ActionResult {
+  let action = generator
+    .generate(Arc::downgrade(thing), name.to_owned(), input)
+    .ok_or_else(|| format!("unsupported action `{name}`"))?;
+  let id = action.get_id();
+
+  thing.write().unwrap().add_action(Arc::new(RwLock::new(action)), input).map_err(|err| err.to_owned())?;
+  thing.write().unwrap().start_action(name.to_owned(), id);
+
+  Ok(())
+}
+
+/// Get the `pulse` (in milliseconds) and `delay` (in seconds) inputs of the `unlock` and `lock` actions.
+fn trigger_input(input: &Input) -> (Option<Duration>, Duration) {
+  let pulse = input.get("pulse").and_then(serde_json::Value::as_u64).map(Duration::from_millis);
+  let delay = input.get("delay").and_then(serde_json::Value::as_u64).map(Duration::from_secs);
+  (pulse, delay.unwrap_or_default())
+}
+
+async fn unlock_door(door: SharedDoor, _thing: &Arc<RwLock<Box<dyn Thing>>>, input: Input) -> ActionResult {
+  let (pulse, delay) = trigger_input(&input);
+  sleep(delay).await;
+
+  Ok(door.write().await.open(pulse).await?)
 }
-action!(UnlockAction, "unlock", unlock_door);
+action!(UnlockAction, "unlock", SharedDoor, unlock_door);
 
-async fn lock_door(door: &mut Box<dyn Any + Send + Sync>) {
-  if let Some(ref mut door) = door.downcast_mut::<GarageDoor>() {
-    door.close().await;
-  } else {
-    unreachable!()
+pub async fn lock_door(door: SharedDoor, thing: &Arc<RwLock<Box<dyn Thing>>>, input: Input) -> ActionResult {
+  let (pulse, delay) = trigger_input(&input);
+  sleep(delay).await;
+
+  if let Err(err) = close_and_wait(&door, pulse).await {
+    // Stopping or opening the door while it is closing is deliberate.
+    if !matches!(err, door_server::Error::Interrupted) {
+      let event = Box::new(BaseEvent::new("close_failed".to_owned(), Some(json!(err.to_string()))));
+      thing.write().unwrap().add_event(event);
+    }
+    return Err(err.into())
   }
+
+  Ok(())
+}
+action!(LockAction, "lock", SharedDoor, lock_door);
+
+async fn stop_door(door: SharedDoor, _thing: &Arc<RwLock<Box<dyn Thing>>>, _input: Input) -> ActionResult {
+  Ok(door.write().await.stop().await?)
+}
+action!(StopAction, "stop", SharedDoor, stop_door);
+
+async fn toggle_door(door: SharedDoor, _thing: &Arc<RwLock<Box<dyn Thing>>>, _input: Input) -> ActionResult {
+  Ok(door.write().await.toggle().await?)
+}
+action!(ToggleAction, "toggle", SharedDoor, toggle_door);
+
+async fn hold_open_door(hold_open: HoldOpenHandle, _thing: &Arc<RwLock<Box<dyn Thing>>>, input: Input) -> ActionResult {
+  let duration = input.get("duration").and_then(serde_json::Value::as_u64).map(Duration::from_secs);
+  hold_open.hold(duration)
+}
+action!(HoldOpenAction, "hold_open", HoldOpenHandle, hold_open_door);
+
+async fn release_door(hold_open: HoldOpenHandle, _thing: &Arc<RwLock<Box<dyn Thing>>>, _input: Input) -> ActionResult {
+  hold_open.release()
+}
+action!(ReleaseAction, "release", HoldOpenHandle, release_door);
+
+/// Get the optional `door` input of the `arm` and `disarm` actions.
+fn door_input(input: &Input) -> Option<String> {
+  input.get("door").and_then(serde_json::Value::as_str).map(str::to_owned)
+}
+
+async fn arm(intrusion: IntrusionHandle, _thing: &Arc<RwLock<Box<dyn Thing>>>, input: Input) -> ActionResult {
+  intrusion.arm(door_input(&input))
+}
+action!(ArmAction, "arm", IntrusionHandle, arm);
+
+async fn disarm(intrusion: IntrusionHandle, _thing: &Arc<RwLock<Box<dyn Thing>>>, input: Input) -> ActionResult {
+  intrusion.disarm(door_input(&input))
 }
-action!(LockAction, "lock", lock_door);
+action!(DisarmAction, "disarm", IntrusionHandle, disarm);
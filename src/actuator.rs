@@ -0,0 +1,96 @@
+use std::{fmt, future::Future, pin::Pin, time::Duration};
+
+use tokio::sync::RwLock;
+
+use crate::{Error, Result};
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Something a door can be told to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capability {
+  Open,
+  Close,
+  Stop,
+  Toggle,
+  Hold,
+}
+
+impl fmt::Display for Capability {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Open => write!(f, "open"),
+      Self::Close => write!(f, "close"),
+      Self::Stop => write!(f, "stop"),
+      Self::Toggle => write!(f, "toggle"),
+      Self::Hold => write!(f, "hold"),
+    }
+  }
+}
+
+/// A door which can be controlled, supporting a subset of [`Capability`].
+///
+/// Methods for capabilities which are not supported fail with [`Error::Unsupported`].
+pub trait Actuator: fmt::Debug + Send + Sync {
+  fn capabilities(&self) -> &'static [Capability];
+
+  fn supports(&self, capability: Capability) -> bool {
+    self.capabilities().contains(&capability)
+  }
+
+  /// Open the door, using a trigger pulse of the given length if applicable.
+  fn open(&mut self, pulse: Option<Duration>) -> BoxFuture<'_, Result<()>>;
+
+  /// Close the door, using a trigger pulse of the given length if applicable.
+  ///
+  /// Doors which take a while to close only start closing, see [`Actuator::closed`].
+  fn close(&mut self, _pulse: Option<Duration>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async { Err(Error::Unsupported(Capability::Close)) })
+  }
+
+  /// Wait until the door is closed after [`Actuator::close`] without borrowing it, so that it can still be stopped
+  /// meanwhile. Returns `None` if [`Actuator::close`] already waits until the door is closed.
+  fn closed(&self) -> Option<BoxFuture<'static, Result<()>>> {
+    None
+  }
+
+  fn stop(&mut self) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async { Err(Error::Unsupported(Capability::Stop)) })
+  }
+
+  /// Close the door if it is open, otherwise open it.
+  fn toggle(&mut self) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async { Err(Error::Unsupported(Capability::Toggle)) })
+  }
+
+  /// Keep the door open until [`Actuator::release`] is called.
+  fn hold(&mut self) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async { Err(Error::Unsupported(Capability::Hold)) })
+  }
+
+  fn release(&mut self) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async { Err(Error::Unsupported(Capability::Hold)) })
+  }
+}
+
+/// Close a door and wait until it is closed, retrying once if it is not closed in time.
+///
+/// The door is only locked while it is triggered, so that it can be stopped while closing.
+pub async fn close_and_wait(door: &RwLock<Box<dyn Actuator>>, pulse: Option<Duration>) -> Result<()> {
+  for _ in 0..2 {
+    let closed = {
+      let mut door = door.write().await;
+      door.close(pulse).await?;
+      door.closed()
+    };
+
+    let Some(closed) = closed else { return Ok(()) };
+    match closed.await {
+      // If the door is still open, closing it again stops it first.
+      Err(Error::CloseFailed) => continue,
+      result => return result,
+    }
+  }
+
+  Err(Error::CloseFailed)
+}
@@ -0,0 +1,84 @@
+use std::{
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
+};
+
+use chrono::{SecondsFormat, Utc};
+use serde_json::json;
+use webthing::{BaseEvent, BaseProperty, Thing};
+
+use crate::set_property;
+
+const UNATTRIBUTED: &str = "unattributed";
+
+/// Attributes door openings to the ekey user whose finger was scanned shortly before.
+#[derive(Clone)]
+pub struct Attribution {
+  window: Duration,
+  last_scan: Arc<Mutex<Option<(String, Instant)>>>,
+}
+
+impl Attribution {
+  pub fn new(window: Duration) -> Self {
+    Self { window, last_scan: Default::default() }
+  }
+
+  pub fn add_to(&self, thing: &mut dyn Thing) {
+    let last_opened_by = json!({
+      "title": "Last Opened By",
+      "type": "string",
+      "description": "Name of the user who last opened the door, or `unattributed`. Empty until the door is opened.",
+      "readOnly": true,
+    });
+    thing.add_property(Box::new(BaseProperty::new(
+      "last_opened_by".into(),
+      json!(""),
+      None,
+      Some(last_opened_by.as_object().unwrap().to_owned()),
+    )));
+
+    let last_opened_at = json!({
+      "title": "Last Opened At",
+      "type": "string",
+      "format": "date-time",
+      "description": "When the door was last opened. Empty until the door is opened.",
+      "readOnly": true,
+    });
+    thing.add_property(Box::new(BaseProperty::new(
+      "last_opened_at".into(),
+      json!(""),
+      None,
+      Some(last_opened_at.as_object().unwrap().to_owned()),
+    )));
+
+    thing.add_available_event(
+      "opened".to_owned(),
+      json!({
+        "description": "The door has been opened by the given user, or `unattributed`.",
+        "type": "string",
+      })
+      .as_object()
+      .unwrap()
+      .to_owned(),
+    );
+  }
+
+  /// Remember a successful finger scan of the given user.
+  pub fn scanned(&self, user: String) {
+    *self.last_scan.lock().unwrap() = Some((user, Instant::now()));
+  }
+
+  /// Publish who opened the door, consuming the last finger scan if it is recent enough.
+  pub fn opened(&self, thing: &mut Box<dyn Thing>) {
+    let user = match self.last_scan.lock().unwrap().take() {
+      Some((user, time)) if time.elapsed() <= self.window => user,
+      _ => UNATTRIBUTED.to_owned(),
+    };
+
+    log::info!("{} opened by {user}.", thing.get_title());
+
+    set_property(&mut *thing, "last_opened_by", json!(user));
+    set_property(&mut *thing, "last_opened_at", json!(Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true)));
+    thing.add_event(Box::new(BaseEvent::new("opened".to_owned(), Some(json!(user)))));
+  }
+}
@@ -0,0 +1,560 @@
+use std::{
+  error::Error,
+  fs::{self, File, OpenOptions},
+  future::Future,
+  io::Write,
+  path::{Path, PathBuf},
+  pin::Pin,
+  sync::{Arc, Mutex},
+  task::{Context, Poll},
+  time::Duration,
+};
+
+use actix_web::{
+  body::{BodySize, BoxBody, MessageBody},
+  web::Bytes,
+};
+use chrono::{SecondsFormat, Utc};
+use hmac::{Hmac, KeyInit, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use door_server::{Actuator, BoxFuture, Capability, Result, config::AuditConfig};
+
+use crate::health::Health;
+
+tokio::task_local! {
+  static ACTOR: String;
+}
+
+/// Recorded for actuations whose requester is not known.
+const UNKNOWN_ACTOR: &str = "unknown";
+
+/// Who requested what is currently being done.
+pub fn actor() -> String {
+  ACTOR.try_with(Clone::clone).unwrap_or_else(|_| UNKNOWN_ACTOR.to_owned())
+}
+
+/// Run a future on behalf of the given actor.
+pub async fn scope<F: Future>(actor: String, f: F) -> F::Output {
+  ACTOR.scope(actor, f).await
+}
+
+/// Run a function on behalf of the given actor.
+pub fn sync_scope<R>(actor: String, f: impl FnOnce() -> R) -> R {
+  ACTOR.sync_scope(actor, f)
+}
+
+/// A response body which is streamed on behalf of an actor.
+///
+/// WebSocket sessions handle their messages, including action requests, while their response body is streamed.
+pub struct ScopedBody {
+  actor: String,
+  body: BoxBody,
+}
+
+impl ScopedBody {
+  pub fn new(actor: String, body: impl MessageBody + 'static) -> Self {
+    Self { actor, body: body.boxed() }
+  }
+}
+
+impl MessageBody for ScopedBody {
+  type Error = Box<dyn Error>;
+
+  fn size(&self) -> BodySize {
+    self.body.size()
+  }
+
+  fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Bytes, Self::Error>>> {
+    let this = self.get_mut();
+    ACTOR.sync_scope(this.actor.clone(), || Pin::new(&mut this.body).poll_next(cx))
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Record {
+  seq: u64,
+  time: String,
+  door: String,
+  action: String,
+  actor: String,
+  result: String,
+}
+
+/// A line of the audit log, authenticated together with the previous line by its MAC.
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+  #[serde(flatten)]
+  record: Record,
+  mac: String,
+}
+
+/// The last entry of the audit log, stored next to it so that truncation can be detected.
+#[derive(Debug, Serialize, Deserialize)]
+struct Head {
+  seq: u64,
+  mac: String,
+  head_mac: String,
+}
+
+fn hmac(key: &[u8], parts: &[&[u8]]) -> String {
+  let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+  for part in parts {
+    mac.update(part);
+    mac.update(b"\n");
+  }
+  hex::encode(mac.finalize().into_bytes())
+}
+
+fn entry_mac(key: &[u8], previous: &str, record: &Record) -> String {
+  hmac(key, &[b"entry", previous.as_bytes(), serde_json::to_string(record).unwrap().as_bytes()])
+}
+
+fn head_mac(key: &[u8], seq: u64, mac: &str) -> String {
+  hmac(key, &[b"head", seq.to_string().as_bytes(), mac.as_bytes()])
+}
+
+fn head_path(path: &Path) -> PathBuf {
+  let mut head = path.as_os_str().to_owned();
+  head.push(".head");
+  head.into()
+}
+
+pub fn read_key(path: &Path) -> std::io::Result<Vec<u8>> {
+  let key = fs::read(path)?;
+  let key = key.trim_ascii();
+  if key.is_empty() {
+    return Err(std::io::Error::other(format!("audit key file {} is empty", path.display())))
+  }
+  Ok(key.to_owned())
+}
+
+fn read(path: &Path) -> std::io::Result<String> {
+  match fs::read(path) {
+    // A line torn by a power cut during an append may end in the middle of a character.
+    Ok(contents) => Ok(String::from_utf8_lossy(&contents).into_owned()),
+    Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(String::new()),
+    Err(err) => Err(err),
+  }
+}
+
+/// Verify the MAC chain of the audit log and its head, returning the number of entries.
+pub fn verify(path: &Path, key: &[u8]) -> Result<u64, Box<dyn Error>> {
+  let mut seq = 0;
+  let mut mac = String::new();
+
+  for (i, line) in read(path)?.lines().enumerate() {
+    let entry = serde_json::from_str::<Entry>(line).map_err(|err| format!("line {}: invalid entry: {err}", i + 1))?;
+    let line = i + 1;
+
+    if entry.record.seq != seq + 1 {
+      return Err(format!("line {line}: expected entry {}, found entry {}", seq + 1, entry.record.seq).into())
+    }
+
+    if entry.mac != entry_mac(key, &mac, &entry.record) {
+      return Err(format!("line {line}: MAC mismatch, the entry has been modified").into())
+    }
+
+    seq = entry.record.seq;
+    mac = entry.mac;
+  }
+
+  // The head is written when the log is initialised, so it is only missing if it has been deleted.
+  let head = match fs::read_to_string(head_path(path)) {
+    Ok(head) => serde_json::from_str::<Head>(&head).map_err(|err| format!("invalid head: {err}"))?,
+    Err(err) if err.kind() == std::io::ErrorKind::NotFound && !path.exists() => {
+      return Err("the log and its head are missing, they have been deleted or the log was never initialised".into())
+    },
+    Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+      return Err("the head is missing, it has been deleted".into())
+    },
+    Err(err) => return Err(format!("reading head failed: {err}").into()),
+  };
+
+  if head.head_mac != head_mac(key, head.seq, &head.mac) {
+    return Err("head MAC mismatch, the head has been modified".into())
+  }
+
+  if head.seq != seq || head.mac != mac {
+    return Err(
+      format!("the log ends at entry {seq}, but its head is at entry {}, it has been truncated", head.seq).into(),
+    )
+  }
+
+  Ok(seq)
+}
+
+struct Log {
+  path: PathBuf,
+  key: Vec<u8>,
+  file: File,
+  seq: u64,
+  mac: String,
+}
+
+impl Log {
+  fn append(&mut self, record: Record) -> std::io::Result<()> {
+    let mac = entry_mac(&self.key, &self.mac, &record);
+    let seq = record.seq;
+    let mut line = serde_json::to_string(&Entry { record, mac: mac.clone() })?;
+    line.push('\n');
+
+    self.file.write_all(line.as_bytes())?;
+    self.file.sync_data()?;
+    self.seq = seq;
+    self.mac = mac;
+
+    self.write_head()
+  }
+
+  /// Replace the head atomically, so that it is never partially written.
+  fn write_head(&self) -> std::io::Result<()> {
+    let head = Head { seq: self.seq, mac: self.mac.clone(), head_mac: head_mac(&self.key, self.seq, &self.mac) };
+    let head_path = head_path(&self.path);
+    let temporary_path = head_path.with_extension("head.tmp");
+    fs::write(&temporary_path, serde_json::to_string(&head)?)?;
+    fs::rename(temporary_path, head_path)
+  }
+}
+
+/// Appends every actuation to a hash-chained log file, with an HMAC per entry.
+#[derive(Clone)]
+pub struct Audit {
+  log: Arc<Mutex<Log>>,
+  health: Health,
+}
+
+impl Audit {
+  /// Create a new, empty audit log. Fails if the log or its head already exist.
+  pub fn init(config: &AuditConfig) -> Result<(), Box<dyn Error>> {
+    let key = read_key(&config.key_file)?;
+
+    let head = head_path(&config.path);
+    if head.exists() {
+      return Err(format!("{} already exists", head.display()).into())
+    }
+    let file = OpenOptions::new().write(true).create_new(true).open(&config.path)?;
+
+    let log = Log { path: config.path.clone(), key, file, seq: 0, mac: String::new() };
+    Ok(log.write_head()?)
+  }
+
+  /// Open the audit log, continuing its chain from the last intact entry.
+  ///
+  /// A new chain is only started by [`Audit::init`], so a log which has been deleted together with its head is
+  /// recorded as a verification failure rather than silently started again.
+  pub fn open(config: &AuditConfig, health: Health) -> Result<Self, Box<dyn Error>> {
+    let key = read_key(&config.key_file)?;
+    let verification = verify(&config.path, &key);
+
+    // Lines which cannot be parsed, e.g. torn by a power cut during an append, are skipped.
+    let contents = read(&config.path)?;
+    let (seq, mac) = match contents.lines().rev().find_map(|line| serde_json::from_str::<Entry>(line).ok()) {
+      Some(entry) => (entry.record.seq, entry.mac),
+      None => (0, String::new()),
+    };
+    let mut file = OpenOptions::new().create(true).append(true).open(&config.path)?;
+
+    // Terminate a torn line, so that the next entry starts on its own line.
+    if !contents.is_empty() && !contents.ends_with('\n') {
+      file.write_all(b"\n")?;
+    }
+
+    let log = Log { path: config.path.clone(), key, file, seq, mac };
+    let audit = Self { log: Arc::new(Mutex::new(log)), health };
+
+    // Continuing the chain replaces the head, so the failure is recorded in the log to keep it detectable.
+    if let Err(err) = verification {
+      audit.health.report("Audit", format!("verification failed: {err}"));
+      audit.record_as("", "verify", "startup".to_owned(), format!("error: {err}"));
+    }
+
+    Ok(audit)
+  }
+
+  pub fn record_as(&self, door: &str, action: &str, actor: String, result: String) {
+    let mut log = self.log.lock().unwrap();
+
+    let record = Record {
+      seq: log.seq + 1,
+      time: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+      door: door.to_owned(),
+      action: action.to_owned(),
+      actor,
+      result,
+    };
+
+    match log.append(record) {
+      Ok(()) => log::info!("Audited entry {}: {}", log.seq, log.mac),
+      Err(err) => self.health.report("Audit", err),
+    }
+  }
+
+  /// Wrap a door, so that all of its actuations are recorded.
+  pub fn wrap(&self, door: &str, actuator: Box<dyn Actuator>) -> Box<dyn Actuator> {
+    Box::new(Audited { door: door.to_owned(), actuator, audit: self.clone() })
+  }
+}
+
+struct Audited {
+  door: String,
+  actuator: Box<dyn Actuator>,
+  audit: Audit,
+}
+
+impl std::fmt::Debug for Audited {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("Audited").field("door", &self.door).field("actuator", &self.actuator).finish()
+  }
+}
+
+fn outcome(result: &Result<()>) -> String {
+  match result {
+    Ok(()) => "ok".to_owned(),
+    Err(err) => format!("error: {err}"),
+  }
+}
+
+impl Audited {
+  /// Record an attempt before actuating the door, so that it is recorded even if the server stops meanwhile, e.g.
+  /// while waiting for a motor lock to move, and the result afterwards.
+  fn audited<'a>(
+    &'a mut self,
+    action: &'static str,
+    actuate: impl FnOnce(&'a mut Box<dyn Actuator>) -> BoxFuture<'a, Result<()>>,
+  ) -> BoxFuture<'a, Result<()>> {
+    let Self { door, actuator, audit } = self;
+
+    let actor = actor();
+    audit.record_as(door, action, actor.clone(), "attempt".to_owned());
+    let actuation = actuate(actuator);
+
+    Box::pin(async move {
+      let result = actuation.await;
+      audit.record_as(door, action, actor, outcome(&result));
+      result
+    })
+  }
+}
+
+impl Actuator for Audited {
+  fn capabilities(&self) -> &'static [Capability] {
+    self.actuator.capabilities()
+  }
+
+  fn open(&mut self, pulse: Option<Duration>) -> BoxFuture<'_, Result<()>> {
+    self.audited("open", |actuator| actuator.open(pulse))
+  }
+
+  fn close(&mut self, pulse: Option<Duration>) -> BoxFuture<'_, Result<()>> {
+    self.audited("close", |actuator| actuator.close(pulse))
+  }
+
+  fn closed(&self) -> Option<BoxFuture<'static, Result<()>>> {
+    let closed = self.actuator.closed()?;
+    let (door, audit, actor) = (self.door.clone(), self.audit.clone(), actor());
+
+    Some(Box::pin(async move {
+      let result = closed.await;
+      audit.record_as(&door, "closed", actor, outcome(&result));
+      result
+    }))
+  }
+
+  fn stop(&mut self) -> BoxFuture<'_, Result<()>> {
+    self.audited("stop", |actuator| actuator.stop())
+  }
+
+  fn toggle(&mut self) -> BoxFuture<'_, Result<()>> {
+    self.audited("toggle", |actuator| actuator.toggle())
+  }
+
+  fn hold(&mut self) -> BoxFuture<'_, Result<()>> {
+    self.audited("hold", |actuator| actuator.hold())
+  }
+
+  fn release(&mut self) -> BoxFuture<'_, Result<()>> {
+    self.audited("release", |actuator| actuator.release())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::env;
+
+  use super::*;
+
+  /// Open a new audit log in an empty temporary directory and record the given number of entries.
+  fn audit(name: &str, entries: usize) -> (PathBuf, Vec<u8>) {
+    let dir = env::temp_dir().join(format!("door-server-audit-{}-{name}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    let config = AuditConfig { path: dir.join("audit.log"), key_file: dir.join("audit.key") };
+    fs::write(&config.key_file, "secret\n").unwrap();
+
+    Audit::init(&config).unwrap();
+    let audit = Audit::open(&config, Health::default()).unwrap();
+    for i in 0..entries {
+      audit.record_as("main-door-1", "open", format!("token:{i}"), "ok".to_owned());
+    }
+
+    (config.path, b"secret".to_vec())
+  }
+
+  fn edit(path: &Path, f: impl FnOnce(Vec<&str>) -> String) {
+    let contents = fs::read_to_string(path).unwrap();
+    fs::write(path, f(contents.lines().collect())).unwrap();
+  }
+
+  #[test]
+  fn verify_intact() {
+    let (path, key) = audit("intact", 3);
+    assert_eq!(verify(&path, &key).unwrap(), 3);
+    assert!(verify(&path, b"other").unwrap_err().to_string().contains("line 1: MAC mismatch"));
+  }
+
+  #[test]
+  fn verify_empty() {
+    let (path, key) = audit("empty", 0);
+    assert_eq!(verify(&path, &key).unwrap(), 0);
+  }
+
+  #[test]
+  fn verify_modified_entry() {
+    let (path, key) = audit("modified", 3);
+    edit(&path, |lines| lines.join("\n").replacen("token:1", "token:2", 1) + "\n");
+    assert!(verify(&path, &key).unwrap_err().to_string().contains("line 2: MAC mismatch"));
+  }
+
+  #[test]
+  fn verify_removed_entry() {
+    let (path, key) = audit("removed", 3);
+    edit(&path, |lines| format!("{}\n{}\n", lines[0], lines[2]));
+    assert!(verify(&path, &key).unwrap_err().to_string().contains("line 2: expected entry 2, found entry 3"));
+  }
+
+  #[test]
+  fn verify_truncated() {
+    let (path, key) = audit("truncated", 3);
+    edit(&path, |lines| format!("{}\n", lines[..2].join("\n")));
+    assert!(verify(&path, &key).unwrap_err().to_string().contains("has been truncated"));
+  }
+
+  #[test]
+  fn verify_deleted() {
+    let (path, key) = audit("deleted", 3);
+    fs::remove_file(&path).unwrap();
+    assert!(verify(&path, &key).unwrap_err().to_string().contains("has been truncated"));
+
+    fs::remove_file(head_path(&path)).unwrap();
+    assert!(verify(&path, &key).unwrap_err().to_string().contains("the log and its head are missing"));
+
+    fs::write(&path, "").unwrap();
+    assert!(verify(&path, &key).unwrap_err().to_string().contains("the head is missing"));
+  }
+
+  #[test]
+  fn init_refuses_existing_log() {
+    let (path, _) = audit("init", 1);
+    let config = AuditConfig { path: path.clone(), key_file: path.with_file_name("audit.key") };
+    assert!(Audit::init(&config).is_err());
+
+    fs::remove_file(&path).unwrap();
+    assert!(Audit::init(&config).is_err());
+  }
+
+  #[test]
+  fn open_records_deleted_log() {
+    let (path, key) = audit("wiped", 3);
+    fs::remove_file(&path).unwrap();
+    fs::remove_file(head_path(&path)).unwrap();
+
+    let config = AuditConfig { path: path.clone(), key_file: path.with_file_name("audit.key") };
+    Audit::open(&config, Health::default()).unwrap();
+
+    let contents = fs::read_to_string(&path).unwrap();
+    let lines = contents.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 1);
+    assert!(lines[0].contains(r#""action":"verify""#) && lines[0].contains("the log and its head are missing"));
+    assert_eq!(verify(&path, &key).unwrap(), 1);
+  }
+
+  #[test]
+  fn verify_modified_head() {
+    let (path, key) = audit("head", 3);
+    let head = fs::read_to_string(head_path(&path)).unwrap().replace(r#""seq":3"#, r#""seq":2"#);
+    fs::write(head_path(&path), head).unwrap();
+    assert!(verify(&path, &key).unwrap_err().to_string().contains("head MAC mismatch"));
+  }
+
+  #[test]
+  fn verify_torn_line() {
+    let (path, key) = audit("torn", 3);
+    edit(&path, |lines| format!("{}\n{{\"seq\":4,\"ti", lines.join("\n")));
+    assert!(verify(&path, &key).unwrap_err().to_string().contains("line 4: invalid entry"));
+  }
+
+  #[test]
+  fn open_continues_after_torn_line() {
+    let (path, key) = audit("continue", 2);
+    edit(&path, |lines| format!("{}\n{{\"seq\":3,\"ti", lines.join("\n")));
+
+    let config = AuditConfig { path: path.clone(), key_file: path.with_file_name("audit.key") };
+    let audit = Audit::open(&config, Health::default()).unwrap();
+    audit.record_as("main-door-1", "open", "button".to_owned(), "ok".to_owned());
+
+    let contents = fs::read_to_string(&path).unwrap();
+    let lines = contents.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 5);
+    assert!(lines[3].contains(r#""seq":3,"#) && lines[3].contains(r#""action":"verify""#));
+    assert!(lines[4].contains(r#""seq":4,"#));
+
+    // The torn line stays detectable.
+    assert!(verify(&path, &key).unwrap_err().to_string().contains("line 3: invalid entry"));
+  }
+
+  #[derive(Debug)]
+  struct Strike;
+
+  impl Actuator for Strike {
+    fn capabilities(&self) -> &'static [Capability] {
+      &[Capability::Open]
+    }
+
+    fn open(&mut self, _pulse: Option<Duration>) -> BoxFuture<'_, Result<()>> {
+      Box::pin(async { Ok(()) })
+    }
+  }
+
+  #[actix_rt::test]
+  async fn records_attempts_and_results() {
+    let (path, key) = audit("actuations", 0);
+    let config = AuditConfig { path: path.clone(), key_file: path.with_file_name("audit.key") };
+    let mut door = Audit::open(&config, Health::default()).unwrap().wrap("main-door-1", Box::new(Strike));
+
+    scope("token:admin".to_owned(), async { door.open(None).await }).await.unwrap();
+    door.close(None).await.unwrap_err();
+
+    let entries = read(&path)
+      .unwrap()
+      .lines()
+      .map(|line| {
+        let record = serde_json::from_str::<Entry>(line).unwrap().record;
+        (record.action, record.actor, record.result)
+      })
+      .collect::<Vec<_>>();
+    let entry = |action: &str, actor: &str, result: &str| (action.to_owned(), actor.to_owned(), result.to_owned());
+    assert_eq!(
+      entries,
+      [
+        entry("open", "token:admin", "attempt"),
+        entry("open", "token:admin", "ok"),
+        entry("close", "unknown", "attempt"),
+        entry("close", "unknown", "error: door does not support `close`"),
+      ]
+    );
+    assert_eq!(verify(&path, &key).unwrap(), 4);
+  }
+}
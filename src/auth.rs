@@ -0,0 +1,358 @@
+use std::{
+  collections::{HashMap, HashSet},
+  fmt,
+};
+
+use actix_web::{
+  Error, HttpResponse,
+  body::{EitherBody, MessageBody},
+  dev::{ServiceRequest, ServiceResponse},
+  http::{Method, header},
+  middleware::Next,
+  web,
+};
+use sha2::{Digest, Sha256};
+
+use door_server::config::{AuthConfig, Role};
+
+use crate::{
+  audit::{self, ScopedBody},
+  tls::ClientCertificate,
+};
+
+/// What a request does, as far as authorisation is concerned.
+#[derive(Debug, PartialEq)]
+enum Access {
+  /// Read the state of a thing.
+  Read,
+  /// Perform or cancel the given action.
+  Act(String),
+  /// Open a WebSocket, which can request any action.
+  WebSocket,
+  /// Set a property.
+  Write,
+  /// Query the history of all things.
+  History,
+  /// Create, list or revoke guest access links.
+  Guests,
+}
+
+impl fmt::Display for Access {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Read => "read".fmt(f),
+      Self::Act(action) => write!(f, "perform `{action}`"),
+      Self::WebSocket => "open a WebSocket".fmt(f),
+      Self::Write => "write".fmt(f),
+      Self::History => "query the history".fmt(f),
+      Self::Guests => "manage guests".fmt(f),
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+struct Principal {
+  name: String,
+  role: Role,
+  doors: Option<HashSet<String>>,
+}
+
+impl Principal {
+  /// Whether the principal may access the given thing, or the list of all things if `None`.
+  fn may_access(&self, thing: Option<&str>) -> bool {
+    match (thing, &self.doors) {
+      (Some(thing), Some(doors)) => doors.contains(thing),
+      _ => true,
+    }
+  }
+
+  fn may(&self, thing: Option<&str>, access: &Access) -> bool {
+    if !self.may_access(thing) {
+      return false
+    }
+
+    match (self.role, access) {
+      (Role::Admin, _) => true,
+      (_, Access::Read) => true,
+      (Role::Guest, Access::Act(action)) => action == "unlock",
+      (Role::Resident, Access::Act(_) | Access::WebSocket) => true,
+      (Role::Viewer | Role::Resident, Access::History) => self.doors.is_none(),
+      _ => false,
+    }
+  }
+}
+
+/// Checks bearer tokens of all requests against hashed tokens from the configuration.
+pub struct Auth {
+  tokens: HashMap<String, Principal>,
+  /// Door ID of every thing, by index, like in the paths of the things.
+  things: Vec<String>,
+  /// Whether admin tokens are only accepted with a client certificate.
+  admin_client_certificate: bool,
+}
+
+impl Auth {
+  pub fn new(config: &AuthConfig, things: Vec<String>, admin_client_certificate: bool) -> Self {
+    let tokens = config
+      .tokens
+      .iter()
+      .map(|token| {
+        let principal = Principal {
+          name: token.name.clone(),
+          role: token.role,
+          doors: token.doors.as_ref().map(|doors| doors.iter().cloned().collect()),
+        };
+        (token.hash.to_ascii_lowercase(), principal)
+      })
+      .collect();
+
+    Self { tokens, things, admin_client_certificate }
+  }
+
+  fn principal(&self, req: &ServiceRequest) -> Option<&Principal> {
+    let authorization = req.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    let token = authorization.strip_prefix("Bearer ")?.trim();
+    self.tokens.get(&hex::encode(Sha256::digest(token)))
+  }
+
+  /// Find the thing and the access of a request, reading the action name from the body if needed.
+  async fn access(&self, req: &mut ServiceRequest) -> (Option<String>, Access) {
+    let path = req.path().trim_matches('/').split('/').map(str::to_owned).collect::<Vec<_>>();
+    let segments = path.iter().map(String::as_str).collect::<Vec<_>>();
+
+    let thing = match segments.as_slice() {
+      [] | [""] => None,
+      ["history"] => return (None, Access::History),
+      ["guests", ..] => return (None, Access::Guests),
+      [thing, ..] => {
+        Some(thing.parse::<usize>().ok().and_then(|i| self.things.get(i).cloned()).unwrap_or_else(|| thing.to_string()))
+      },
+    };
+
+    let is_websocket = req
+      .headers()
+      .get(header::UPGRADE)
+      .and_then(|upgrade| upgrade.to_str().ok())
+      .is_some_and(|upgrade| upgrade.eq_ignore_ascii_case("websocket"));
+
+    let access = match (req.method(), segments.as_slice()) {
+      (&Method::GET, [_]) if is_websocket => Access::WebSocket,
+      (&Method::GET | &Method::HEAD, _) => Access::Read,
+      (&Method::POST, [_, "actions"]) => {
+        // The action is the only key of the body, which is put back for the handler.
+        let body = req.extract::<web::Bytes>().await.unwrap_or_default();
+        let action = serde_json::from_slice::<serde_json::Map<String, serde_json::Value>>(&body)
+          .ok()
+          .filter(|request| request.len() == 1)
+          .and_then(|request| request.keys().next().cloned());
+        req.set_payload(body.into());
+
+        match action {
+          Some(action) => Access::Act(action),
+          None => Access::Write,
+        }
+      },
+      (&Method::POST, [_, "actions", action]) | (&Method::PUT | &Method::DELETE, [_, "actions", action, _]) => {
+        Access::Act(action.to_string())
+      },
+      _ => Access::Write,
+    };
+
+    (thing, access)
+  }
+}
+
+/// Handle a request on behalf of the given actor, including WebSocket messages streamed in its response.
+async fn call_as(
+  actor: String,
+  req: ServiceRequest,
+  next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<EitherBody<ScopedBody>>, Error> {
+  let response = audit::scope(actor.clone(), next.call(req)).await?;
+  Ok(response.map_body(|_, body| EitherBody::left(ScopedBody::new(actor, body))))
+}
+
+/// Reject requests without a valid token or with a token whose role does not allow them.
+pub async fn authorize(
+  mut req: ServiceRequest,
+  next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<EitherBody<ScopedBody>>, Error> {
+  let peer = req.peer_addr().map(|addr| addr.ip().to_string()).unwrap_or_else(|| "unknown".to_owned());
+
+  // Without tokens, for CORS preflight requests, or for guest access links, which contain their own token,
+  // authorisation is not needed.
+  let auth = match req.app_data::<web::Data<Auth>>() {
+    Some(auth) if req.method() != Method::OPTIONS && !req.path().starts_with("/guest/") => auth.clone(),
+    _ => return call_as(format!("http:{peer}"), req, next).await,
+  };
+
+  let Some(principal) = auth.principal(&req).cloned() else {
+    log::warn!("Rejected {} {} from {peer}: missing or invalid token.", req.method(), req.path());
+    let response = HttpResponse::Unauthorized().insert_header((header::WWW_AUTHENTICATE, "Bearer")).finish();
+    return Ok(req.into_response(response).map_into_right_body())
+  };
+
+  if principal.role == Role::Admin && auth.admin_client_certificate && req.conn_data::<ClientCertificate>().is_none() {
+    log::warn!(
+      "Rejected {} {} from {peer}: token {} needs a client certificate.",
+      req.method(),
+      req.path(),
+      principal.name
+    );
+    return Ok(req.into_response(HttpResponse::Forbidden().finish()).map_into_right_body())
+  }
+
+  let (thing, access) = auth.access(&mut req).await;
+  if !principal.may(thing.as_deref(), &access) {
+    log::warn!("Rejected {} {} from {peer}: token {} may not {access}.", req.method(), req.path(), principal.name);
+    return Ok(req.into_response(HttpResponse::Forbidden().finish()).map_into_right_body())
+  }
+
+  call_as(format!("token:{}", principal.name), req, next).await
+}
+
+/// Require a token for all requests.
+pub fn configure(config: &mut web::ServiceConfig, auth: web::Data<Auth>) {
+  config.app_data(auth);
+}
+
+#[cfg(test)]
+mod tests {
+  use actix_web::{App, http::StatusCode, middleware::from_fn, test::TestRequest};
+  use door_server::config::TokenConfig;
+
+  use super::*;
+
+  const THINGS: [&str; 2] = ["main-door-1", "cellar-door-1"];
+
+  fn auth(admin_client_certificate: bool) -> Auth {
+    let tokens = [("admin", Role::Admin, None), ("viewer", Role::Viewer, None), ("guest", Role::Guest, None)]
+      .into_iter()
+      .chain([("cellar", Role::Resident, Some(vec!["cellar-door-1".to_owned()]))])
+      .map(|(name, role, doors)| TokenConfig {
+        name: name.to_owned(),
+        hash: hex::encode(Sha256::digest(name)),
+        role,
+        doors,
+      })
+      .collect();
+
+    let things = THINGS.iter().map(|thing| thing.to_string()).collect();
+    Auth::new(&AuthConfig { tokens }, things, admin_client_certificate)
+  }
+
+  #[test]
+  fn role_matrix() {
+    let act = |action: &str| Access::Act(action.to_owned());
+    let accesses =
+      [Access::Read, act("unlock"), act("lock"), Access::WebSocket, Access::Write, Access::History, Access::Guests];
+
+    let cases = [
+      (Role::Viewer, [true, false, false, false, false, true, false]),
+      (Role::Guest, [true, true, false, false, false, false, false]),
+      (Role::Resident, [true, true, true, true, false, true, false]),
+      (Role::Admin, [true, true, true, true, true, true, true]),
+    ];
+
+    for (role, expected) in cases {
+      let principal = Principal { name: "test".to_owned(), role, doors: None };
+
+      for (access, expected) in accesses.iter().zip(expected) {
+        let thing = if matches!(access, Access::History | Access::Guests) { None } else { Some("main-door-1") };
+        assert_eq!(principal.may(thing, access), expected, "{role:?} may {access}");
+      }
+    }
+  }
+
+  #[test]
+  fn restricted_doors() {
+    let doors = Some(["cellar-door-1".to_owned()].into());
+    let principal = Principal { name: "cellar".to_owned(), role: Role::Resident, doors };
+
+    assert!(principal.may(Some("cellar-door-1"), &Access::Act("unlock".to_owned())));
+    assert!(!principal.may(Some("main-door-1"), &Access::Act("unlock".to_owned())));
+    assert!(!principal.may(Some("main-door-1"), &Access::Read));
+    assert!(!principal.may(Some("urn:dev:ops:32473-house"), &Access::Read));
+    assert!(principal.may(None, &Access::Read));
+    assert!(!principal.may(None, &Access::History));
+  }
+
+  #[actix_rt::test]
+  async fn access() {
+    let auth = auth(false);
+
+    let cases = [
+      (Method::GET, "/", "", None, Access::Read),
+      (Method::GET, "/history", "", None, Access::History),
+      (Method::DELETE, "/guests/1", "", None, Access::Guests),
+      (Method::GET, "/0/properties", "", Some("main-door-1"), Access::Read),
+      (Method::GET, "/cellar-door-1", "", Some("cellar-door-1"), Access::Read),
+      (Method::POST, "/1/actions", r#"{"lock": {}}"#, Some("cellar-door-1"), Access::Act("lock".to_owned())),
+      (Method::POST, "/1/actions", r#"{"lock": {}, "unlock": {}}"#, Some("cellar-door-1"), Access::Write),
+      (Method::POST, "/0/actions/unlock", "{}", Some("main-door-1"), Access::Act("unlock".to_owned())),
+      (Method::DELETE, "/0/actions/unlock/1", "", Some("main-door-1"), Access::Act("unlock".to_owned())),
+      (Method::PUT, "/0/properties/open", "{}", Some("main-door-1"), Access::Write),
+    ];
+
+    for (method, path, body, thing, access) in cases {
+      let mut req = TestRequest::default().method(method).uri(path).set_payload(body).to_srv_request();
+      assert_eq!(auth.access(&mut req).await, (thing.map(str::to_owned), access), "{path}");
+
+      // The body is still available to the handler.
+      assert_eq!(req.extract::<web::Bytes>().await.unwrap(), body.as_bytes());
+    }
+
+    let mut req = TestRequest::get().uri("/1").insert_header((header::UPGRADE, "websocket")).to_srv_request();
+    assert_eq!(auth.access(&mut req).await, (Some("cellar-door-1".to_owned()), Access::WebSocket));
+  }
+
+  async fn status(auth: Auth, method: Method, path: &str, token: Option<&str>, body: &str) -> StatusCode {
+    let auth = web::Data::new(auth);
+    let app = actix_web::test::init_service(
+      App::new()
+        .wrap(from_fn(authorize))
+        .configure(|config| configure(config, auth))
+        .default_service(web::to(HttpResponse::Ok)),
+    )
+    .await;
+
+    let mut req = TestRequest::default().method(method).uri(path).set_payload(body.to_owned());
+    if let Some(token) = token {
+      req = req.insert_header((header::AUTHORIZATION, format!("Bearer {token}")));
+    }
+
+    actix_web::test::call_service(&app, req.to_request()).await.status()
+  }
+
+  #[actix_rt::test]
+  async fn authorize_requests() {
+    let unlock = r#"{"unlock": {}}"#;
+
+    let cases = [
+      (Method::GET, "/", None, "", StatusCode::UNAUTHORIZED),
+      (Method::GET, "/", Some("wrong"), "", StatusCode::UNAUTHORIZED),
+      (Method::OPTIONS, "/0/actions", None, "", StatusCode::OK),
+      (Method::GET, "/guest/token", None, "", StatusCode::OK),
+      (Method::GET, "/0", Some("viewer"), "", StatusCode::OK),
+      (Method::POST, "/0/actions", Some("viewer"), unlock, StatusCode::FORBIDDEN),
+      (Method::POST, "/0/actions", Some("guest"), unlock, StatusCode::OK),
+      (Method::POST, "/0/actions", Some("guest"), r#"{"hold_open": {}}"#, StatusCode::FORBIDDEN),
+      (Method::POST, "/1/actions", Some("cellar"), unlock, StatusCode::OK),
+      (Method::POST, "/0/actions", Some("cellar"), unlock, StatusCode::FORBIDDEN),
+      (Method::GET, "/history", Some("cellar"), "", StatusCode::FORBIDDEN),
+      (Method::POST, "/guests", Some("cellar"), "{}", StatusCode::FORBIDDEN),
+      (Method::PUT, "/0/properties/open", Some("admin"), "{}", StatusCode::OK),
+      (Method::POST, "/guests", Some("admin"), "{}", StatusCode::OK),
+    ];
+
+    for (method, path, token, body, expected) in cases {
+      assert_eq!(status(auth(false), method.clone(), path, token, body).await, expected, "{method} {path} {token:?}");
+    }
+  }
+
+  #[actix_rt::test]
+  async fn admin_requires_client_certificate() {
+    assert_eq!(status(auth(true), Method::GET, "/", Some("admin"), "").await, StatusCode::FORBIDDEN);
+    assert_eq!(status(auth(true), Method::GET, "/", Some("viewer"), "").await, StatusCode::OK);
+  }
+}
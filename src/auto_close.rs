@@ -0,0 +1,163 @@
+use std::{
+  sync::{
+    Arc, RwLock,
+    atomic::{AtomicBool, Ordering},
+  },
+  time::Duration,
+};
+
+use actix_rt::time::{Instant, sleep};
+use chrono::{Local, TimeDelta};
+use serde_json::json;
+use tokio::sync::{Notify, watch};
+use webthing::{BaseEvent, Thing};
+
+use door_server::{Motion, config::AutoCloseConfig};
+
+use crate::{
+  action::{SharedDoor, lock_door},
+  audit,
+  indicator::Indicator,
+};
+
+const BLINK_INTERVAL: Duration = Duration::from_millis(500);
+
+enum Warning {
+  Elapsed,
+  Aborted,
+  Closed,
+}
+
+/// Allows aborting a pending auto-close, e.g. by pressing the wall button.
+#[derive(Debug, Default)]
+pub struct AutoCloseHandle {
+  warning: AtomicBool,
+  abort: Notify,
+}
+
+impl AutoCloseHandle {
+  /// Abort the auto-close if it is currently warning. Returns whether it was aborted.
+  pub fn abort(&self) -> bool {
+    if self.warning.swap(false, Ordering::SeqCst) {
+      self.abort.notify_one();
+      true
+    } else {
+      false
+    }
+  }
+}
+
+/// Closes a garage door after it has been left open, blinking its LEDs first.
+pub struct AutoClose {
+  config: AutoCloseConfig,
+  door: SharedDoor,
+  thing: Arc<RwLock<Box<dyn Thing>>>,
+  motion: watch::Receiver<Motion>,
+  indicator: Indicator,
+  handle: Arc<AutoCloseHandle>,
+}
+
+impl AutoClose {
+  pub fn new(
+    config: AutoCloseConfig,
+    door: SharedDoor,
+    thing: Arc<RwLock<Box<dyn Thing>>>,
+    motion: watch::Receiver<Motion>,
+    indicator: Indicator,
+  ) -> Self {
+    thing.write().unwrap().add_available_event(
+      "auto_close".to_owned(),
+      json!({
+        "description": "The door is about to be closed automatically.",
+        "type": "string",
+        "enum": ["warning", "aborted", "closing"],
+      })
+      .as_object()
+      .unwrap()
+      .to_owned(),
+    );
+
+    Self { config, door, thing, motion, indicator, handle: Default::default() }
+  }
+
+  pub fn handle(&self) -> Arc<AutoCloseHandle> {
+    self.handle.clone()
+  }
+
+  /// Time until the door should be closed, starting now.
+  fn delay(&self) -> Duration {
+    let at = self.config.at.map(|at| {
+      let now = Local::now().naive_local();
+      let mut next = now.date().and_time(at);
+      if next <= now {
+        next += TimeDelta::days(1);
+      }
+      (next - now).to_std().unwrap_or_default()
+    });
+
+    [self.config.after, at].into_iter().flatten().min().unwrap_or(Duration::MAX)
+  }
+
+  fn notify(&self, status: &str) {
+    let event = Box::new(BaseEvent::new("auto_close".to_owned(), Some(json!(status))));
+    self.thing.write().unwrap().add_event(event);
+  }
+
+  async fn warn(&mut self) -> Warning {
+    let deadline = Instant::now() + self.config.warning;
+    let mut on = true;
+
+    loop {
+      self.indicator.show_warning(on).await;
+      on = !on;
+
+      tokio::select! {
+        _ = sleep(BLINK_INTERVAL) => if Instant::now() >= deadline { return Warning::Elapsed },
+        _ = self.handle.abort.notified() => return Warning::Aborted,
+        _ = self.motion.wait_for(|motion| *motion == Motion::Closed) => return Warning::Closed,
+      }
+    }
+  }
+
+  pub async fn run(mut self) {
+    let name = self.thing.read().unwrap().get_title();
+
+    loop {
+      if self.motion.wait_for(|motion| *motion != Motion::Closed).await.is_err() {
+        return
+      }
+
+      let delay = self.delay();
+      tokio::select! {
+        _ = sleep(delay) => (),
+        _ = self.motion.wait_for(|motion| *motion == Motion::Closed) => continue,
+      }
+
+      log::info!("{name} has been left open, closing it in {:?}.", self.config.warning);
+      self.notify("warning");
+
+      self.handle.warning.store(true, Ordering::SeqCst);
+      let warning = self.warn().await;
+      self.handle.warning.store(false, Ordering::SeqCst);
+
+      self.indicator.refresh().await;
+
+      match warning {
+        Warning::Elapsed => {
+          log::info!("Closing {name} automatically.");
+          self.notify("closing");
+
+          let close = lock_door(self.door.clone(), &self.thing, Default::default());
+          if let Err(err) = audit::scope("auto_close".to_owned(), close).await {
+            log::error!("Closing {name} automatically failed: {err}");
+          }
+        },
+        Warning::Aborted => {
+          log::info!("Closing {name} automatically was aborted.");
+          self.notify("aborted");
+        },
+        Warning::Closed => (),
+      }
+    }
+  }
+}
@@ -0,0 +1,732 @@
+use std::{
+  collections::{HashMap, HashSet},
+  fmt, fs, io,
+  path::{Path, PathBuf},
+  str::FromStr,
+  time::Duration,
+};
+
+use chrono::NaiveTime;
+use serde::{Deserialize, Deserializer, de};
+
+use crate::{hal, led::Segment};
+
+#[derive(Debug)]
+pub enum ConfigError {
+  Io(io::Error),
+  Parse(toml::de::Error),
+  Invalid { key: String, message: String },
+}
+
+impl fmt::Display for ConfigError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Io(err) => write!(f, "failed to read configuration: {err}"),
+      Self::Parse(err) => write!(f, "failed to parse configuration: {err}"),
+      Self::Invalid { key, message } => write!(f, "invalid configuration at `{key}`: {message}"),
+    }
+  }
+}
+
+impl std::error::Error for ConfigError {}
+
+fn invalid(key: impl Into<String>, message: impl Into<String>) -> ConfigError {
+  ConfigError::Invalid { key: key.into(), message: message.into() }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+  #[serde(default)]
+  pub board: BoardConfig,
+  #[serde(default)]
+  pub doors: Vec<DoorConfig>,
+  #[serde(default)]
+  pub buttons: Vec<ButtonConfig>,
+  #[serde(default)]
+  pub ekey: EkeyConfig,
+  #[serde(default)]
+  pub history: HistoryConfig,
+  #[serde(default)]
+  pub guests: GuestsConfig,
+  pub intrusion: Option<IntrusionConfig>,
+  pub mqtt: Option<MqttConfig>,
+  pub auth: Option<AuthConfig>,
+  pub tls: Option<TlsConfig>,
+  pub audit: Option<AuditConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BoardConfig {
+  /// Whether the WS2812 LED ring is connected.
+  #[serde(default = "default_true")]
+  pub ring: bool,
+}
+
+impl Default for BoardConfig {
+  fn default() -> Self {
+    Self { ring: true }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DoorType {
+  /// A door with an electric strike.
+  Door,
+  /// A garage door with open, stop and close buttons.
+  Garage,
+  /// A door with a motorised deadbolt, using `open` to unlock, `close` to lock and `bolt` to sense the bolt.
+  MotorLock,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DoorConfig {
+  pub id: String,
+  pub name: String,
+  #[serde(rename = "type")]
+  pub ty: DoorType,
+  pub pins: DoorPins,
+  pub segment: Option<Segment>,
+  /// Time a garage door needs to fully open or close, or a motor lock needs to throw or retract its bolt.
+  #[serde(default, with = "humantime_serde")]
+  pub travel_time: Option<Duration>,
+  /// Time to wait for a garage door to report being closed before retrying or giving up.
+  #[serde(default, with = "humantime_serde")]
+  pub close_timeout: Option<Duration>,
+  pub auto_close: Option<AutoCloseConfig>,
+  /// Longest time a door may be held open.
+  #[serde(default, with = "humantime_serde")]
+  pub max_hold_open: Option<Duration>,
+  /// Length of the pulses triggering the door.
+  #[serde(default, with = "humantime_serde")]
+  pub pulse: Option<Duration>,
+  /// Longest pulse which can be requested via the `pulse` input of the `unlock` and `lock` actions.
+  #[serde(default, with = "humantime_serde")]
+  pub max_pulse: Option<Duration>,
+  /// Longest delay which can be requested via the `delay` input of the `unlock` and `lock` actions.
+  #[serde(default, with = "humantime_serde")]
+  pub max_delay: Option<Duration>,
+  /// Time to wait after stopping a garage door before moving it again.
+  #[serde(default, with = "humantime_serde")]
+  pub stop_settle: Option<Duration>,
+  pub left_open: Option<LeftOpenConfig>,
+}
+
+impl DoorConfig {
+  pub const DEFAULT_TRAVEL_TIME: Duration = Duration::from_secs(20);
+  pub const DEFAULT_BOLT_TRAVEL_TIME: Duration = Duration::from_secs(3);
+  pub const DEFAULT_MAX_HOLD_OPEN: Duration = Duration::from_secs(8 * 60 * 60);
+  pub const DEFAULT_MAX_PULSE: Duration = Duration::from_secs(5);
+  pub const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(60);
+  pub const DEFAULT_STOP_SETTLE: Duration = Duration::from_millis(500);
+
+  pub fn travel_time(&self) -> Duration {
+    self.travel_time.unwrap_or(match self.ty {
+      DoorType::MotorLock => Self::DEFAULT_BOLT_TRAVEL_TIME,
+      _ => Self::DEFAULT_TRAVEL_TIME,
+    })
+  }
+
+  pub fn max_hold_open(&self) -> Duration {
+    self.max_hold_open.unwrap_or(Self::DEFAULT_MAX_HOLD_OPEN)
+  }
+
+  pub fn pulse(&self) -> Duration {
+    self.pulse.unwrap_or(hal::DEFAULT_PULSE)
+  }
+
+  pub fn max_pulse(&self) -> Duration {
+    self.max_pulse.unwrap_or(Self::DEFAULT_MAX_PULSE)
+  }
+
+  pub fn max_delay(&self) -> Duration {
+    self.max_delay.unwrap_or(Self::DEFAULT_MAX_DELAY)
+  }
+
+  pub fn stop_settle(&self) -> Duration {
+    self.stop_settle.unwrap_or(Self::DEFAULT_STOP_SETTLE)
+  }
+
+  pub fn close_timeout(&self) -> Duration {
+    self.close_timeout.unwrap_or_else(|| self.travel_time() + Duration::from_secs(10))
+  }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DoorPins {
+  pub open: u8,
+  pub stop: Option<u8>,
+  pub close: Option<u8>,
+  pub contact: u8,
+  pub bolt: Option<u8>,
+}
+
+/// Closes a garage door which has been left open.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AutoCloseConfig {
+  /// Close the door after it has been open for this long.
+  #[serde(default, with = "humantime_serde")]
+  pub after: Option<Duration>,
+  /// Close the door if it is still open at this time of day.
+  #[serde(default, deserialize_with = "deserialize_time_of_day")]
+  pub at: Option<NaiveTime>,
+  /// How long to blink the LEDs before closing the door.
+  #[serde(default = "default_warning", with = "humantime_serde")]
+  pub warning: Duration,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LeftOpenConfig {
+  /// Raise an alarm after the door has been open for this long.
+  #[serde(with = "humantime_serde")]
+  pub after: Duration,
+  /// Repeat the alarm at this interval until the door is closed.
+  #[serde(default, with = "humantime_serde")]
+  pub repeat: Option<Duration>,
+  #[serde(default)]
+  pub notify: Vec<NotifyConfig>,
+}
+
+fn deserialize_time_of_day<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<NaiveTime>, D::Error> {
+  let s = String::deserialize(deserializer)?;
+  NaiveTime::parse_from_str(&s, "%H:%M")
+    .map(Some)
+    .map_err(|_| de::Error::custom(format!("invalid time of day `{s}`, expected HH:MM")))
+}
+
+fn default_warning() -> Duration {
+  Duration::from_secs(30)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ButtonType {
+  /// A door bell, emitting a `bell` event on its door.
+  Bell,
+  /// A button toggling its garage door between open and closed, or its motor lock between locked and unlocked.
+  Toggle,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ButtonConfig {
+  #[serde(rename = "type")]
+  pub ty: ButtonType,
+  pub pin: u8,
+  pub door: String,
+  pub led: Option<LedPins>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LedPins {
+  pub red: u8,
+  pub green: u8,
+  pub blue: u8,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EkeyConfig {
+  #[serde(default = "default_ekey_port")]
+  pub port: u16,
+  /// Maps finger scanner names to door IDs.
+  #[serde(default)]
+  pub scanners: HashMap<String, String>,
+  /// How long after a finger scan an opening of the door is attributed to the scanned user.
+  #[serde(default = "default_attribution_window", with = "humantime_serde")]
+  pub attribution_window: Duration,
+}
+
+impl Default for EkeyConfig {
+  fn default() -> Self {
+    Self { port: default_ekey_port(), scanners: HashMap::new(), attribution_window: default_attribution_window() }
+  }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HistoryConfig {
+  /// Whether to record state changes, actions and events.
+  #[serde(default = "default_true")]
+  pub enabled: bool,
+  /// Path of the SQLite database.
+  #[serde(default = "default_history_path")]
+  pub path: PathBuf,
+}
+
+impl Default for HistoryConfig {
+  fn default() -> Self {
+    Self { enabled: true, path: default_history_path() }
+  }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GuestsConfig {
+  /// Whether guest access links can be created.
+  #[serde(default = "default_true")]
+  pub enabled: bool,
+  /// Path of the SQLite database with guest grants and their uses.
+  #[serde(default = "default_guests_path")]
+  pub path: PathBuf,
+}
+
+impl Default for GuestsConfig {
+  fn default() -> Self {
+    Self { enabled: true, path: default_guests_path() }
+  }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuditConfig {
+  /// Path of the hash-chained audit log.
+  #[serde(default = "default_audit_path")]
+  pub path: PathBuf,
+  /// File with the secret key for the HMAC chain.
+  pub key_file: PathBuf,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IntrusionConfig {
+  /// IDs of the doors which can be armed.
+  pub doors: Vec<String>,
+  /// How long after an unlock or finger scan an opening of an armed door is expected.
+  #[serde(default = "default_intrusion_window", with = "humantime_serde")]
+  pub window: Duration,
+  #[serde(default)]
+  pub notify: Vec<NotifyConfig>,
+  /// Where the armed doors are stored, so that they stay armed across restarts.
+  #[serde(default = "default_intrusion_path")]
+  pub path: PathBuf,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MqttConfig {
+  pub host: String,
+  #[serde(default = "default_mqtt_port")]
+  pub port: u16,
+  #[serde(default = "default_mqtt_client_id")]
+  pub client_id: String,
+  /// Prefix of all topics.
+  #[serde(default = "default_mqtt_prefix")]
+  pub prefix: String,
+  pub username: Option<String>,
+  pub password: Option<String>,
+  /// Whether to publish Home Assistant discovery configs.
+  #[serde(default = "default_true")]
+  pub discovery: bool,
+  #[serde(default = "default_discovery_prefix")]
+  pub discovery_prefix: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuthConfig {
+  #[serde(default)]
+  pub tokens: Vec<TokenConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TokenConfig {
+  /// Name of the token, used for logging.
+  pub name: String,
+  /// Hex-encoded SHA-256 hash of the token.
+  pub hash: String,
+  pub role: Role,
+  /// IDs of the doors the token may access, or all doors and the house if omitted.
+  pub doors: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+  /// PEM file with the certificate chain, reloaded when it changes.
+  pub cert: PathBuf,
+  /// PEM file with the private key, reloaded when it changes.
+  pub key: PathBuf,
+  /// PEM file with the CA certificates for client certificates, which admin tokens require if set.
+  pub client_ca: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Role {
+  /// May view doors.
+  Viewer,
+  /// May view and unlock doors.
+  Guest,
+  /// May view doors and perform all of their actions.
+  Resident,
+  /// May do everything.
+  Admin,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum NotifyConfig {
+  /// Run a command with the `EVENT`, `DOOR_ID` and `DOOR_NAME` environment variables set.
+  Command { command: Vec<String> },
+}
+
+fn default_true() -> bool {
+  true
+}
+
+fn default_ekey_port() -> u16 {
+  56000
+}
+
+fn default_attribution_window() -> Duration {
+  Duration::from_secs(30)
+}
+
+fn default_intrusion_window() -> Duration {
+  Duration::from_secs(30)
+}
+
+fn default_mqtt_port() -> u16 {
+  1883
+}
+
+fn default_mqtt_client_id() -> String {
+  "door-server".to_owned()
+}
+
+fn default_mqtt_prefix() -> String {
+  "door-server".to_owned()
+}
+
+fn default_discovery_prefix() -> String {
+  "homeassistant".to_owned()
+}
+
+fn default_history_path() -> PathBuf {
+  PathBuf::from("/var/lib/door-server/history.sqlite3")
+}
+
+fn default_guests_path() -> PathBuf {
+  PathBuf::from("/var/lib/door-server/guests.sqlite3")
+}
+
+fn default_intrusion_path() -> PathBuf {
+  PathBuf::from("/var/lib/door-server/intrusion.json")
+}
+
+fn default_audit_path() -> PathBuf {
+  PathBuf::from("/var/lib/door-server/audit.log")
+}
+
+impl FromStr for Config {
+  type Err = ConfigError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let config: Self = toml::from_str(s).map_err(ConfigError::Parse)?;
+    config.validate()?;
+    Ok(config)
+  }
+}
+
+impl Config {
+  pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+    fs::read_to_string(path).map_err(ConfigError::Io)?.parse()
+  }
+
+  pub fn door(&self, id: &str) -> Option<&DoorConfig> {
+    self.doors.iter().find(|door| door.id == id)
+  }
+
+  fn validate(&self) -> Result<(), ConfigError> {
+    let mut pins = HashMap::new();
+    let mut use_pin = |key: String, pin: u8| match pins.insert(pin, key.clone()) {
+      Some(other) => Err(invalid(key, format!("pin {pin} is already used by `{other}`"))),
+      None => Ok(()),
+    };
+
+    let mut ids = HashSet::new();
+    let mut segments = HashMap::new();
+
+    for (i, door) in self.doors.iter().enumerate() {
+      let key = format!("doors[{i}]");
+
+      if door.id.is_empty() {
+        return Err(invalid(format!("{key}.id"), "must not be empty"))
+      }
+
+      if !ids.insert(door.id.as_str()) {
+        return Err(invalid(format!("{key}.id"), format!("duplicate door ID `{}`", door.id)))
+      }
+
+      if let Some(segment) = door.segment
+        && let Some(other) = segments.insert(segment, door.id.as_str())
+      {
+        return Err(invalid(format!("{key}.segment"), format!("segment is already used by door `{other}`")))
+      }
+
+      use_pin(format!("{key}.pins.open"), door.pins.open)?;
+      use_pin(format!("{key}.pins.contact"), door.pins.contact)?;
+
+      let (required, kind): (&[&str], _) = match door.ty {
+        DoorType::Door => (&[], "doors"),
+        DoorType::Garage => (&["stop", "close"], "garage doors"),
+        DoorType::MotorLock => (&["close", "bolt"], "motor locks"),
+      };
+
+      for (name, pin) in [("stop", door.pins.stop), ("close", door.pins.close), ("bolt", door.pins.bolt)] {
+        let key = format!("{key}.pins.{name}");
+
+        match (required.contains(&name), pin) {
+          (false, Some(_)) => return Err(invalid(key, format!("is not supported for {kind}"))),
+          (true, None) => return Err(invalid(key, format!("is required for {kind}"))),
+          (_, Some(pin)) => use_pin(key, pin)?,
+          (_, None) => (),
+        }
+      }
+
+      if door.ty == DoorType::Door && door.travel_time.is_some() {
+        return Err(invalid(format!("{key}.travel_time"), "is not supported for doors"))
+      }
+
+      if door.ty != DoorType::Door && door.max_hold_open.is_some() {
+        return Err(invalid(format!("{key}.max_hold_open"), format!("is not supported for {kind}")))
+      }
+
+      if door.ty == DoorType::MotorLock {
+        for (name, is_set) in [("pulse", door.pulse.is_some()), ("max_pulse", door.max_pulse.is_some())] {
+          if is_set {
+            return Err(invalid(format!("{key}.{name}"), "is not supported for motor locks"))
+          }
+        }
+      }
+
+      if door.pulse().is_zero() {
+        return Err(invalid(format!("{key}.pulse"), "must not be zero"))
+      }
+
+      if door.pulse() > door.max_pulse() {
+        return Err(invalid(format!("{key}.pulse"), "must not be longer than `max_pulse`"))
+      }
+
+      if let Some(auto_close) = &door.auto_close
+        && auto_close.after.is_none()
+        && auto_close.at.is_none()
+      {
+        return Err(invalid(format!("{key}.auto_close"), "requires `after` or `at`"))
+      }
+
+      if let Some(left_open) = &door.left_open {
+        let key = format!("{key}.left_open");
+
+        if left_open.after.is_zero() {
+          return Err(invalid(format!("{key}.after"), "must not be zero"))
+        }
+
+        if left_open.repeat.is_some_and(|repeat| repeat.is_zero()) {
+          return Err(invalid(format!("{key}.repeat"), "must not be zero"))
+        }
+
+        validate_notify(&format!("{key}.notify"), &left_open.notify)?;
+      }
+
+      if door.ty != DoorType::Garage {
+        for (name, is_set) in [
+          ("close_timeout", door.close_timeout.is_some()),
+          ("stop_settle", door.stop_settle.is_some()),
+          ("auto_close", door.auto_close.is_some()),
+        ] {
+          if is_set {
+            return Err(invalid(format!("{key}.{name}"), "is only supported for garage doors"))
+          }
+        }
+      }
+    }
+
+    for (i, button) in self.buttons.iter().enumerate() {
+      let key = format!("buttons[{i}]");
+
+      use_pin(format!("{key}.pin"), button.pin)?;
+
+      match self.door(&button.door) {
+        Some(door) => {
+          if button.ty == ButtonType::Toggle && door.ty == DoorType::Door {
+            return Err(invalid(format!("{key}.door"), format!("door `{}` cannot be toggled", door.id)))
+          }
+        },
+        None => return Err(invalid(format!("{key}.door"), format!("unknown door `{}`", button.door))),
+      }
+
+      if let Some(led) = &button.led {
+        use_pin(format!("{key}.led.red"), led.red)?;
+        use_pin(format!("{key}.led.green"), led.green)?;
+        use_pin(format!("{key}.led.blue"), led.blue)?;
+      }
+    }
+
+    for (scanner, door) in &self.ekey.scanners {
+      if self.door(door).is_none() {
+        return Err(invalid(format!("ekey.scanners.{scanner}"), format!("unknown door `{door}`")))
+      }
+    }
+
+    if let Some(intrusion) = &self.intrusion {
+      let mut doors = HashSet::new();
+
+      for (i, door) in intrusion.doors.iter().enumerate() {
+        let key = format!("intrusion.doors[{i}]");
+
+        if self.door(door).is_none() {
+          return Err(invalid(key, format!("unknown door `{door}`")))
+        }
+
+        if !doors.insert(door) {
+          return Err(invalid(key, format!("duplicate door `{door}`")))
+        }
+      }
+
+      validate_notify("intrusion.notify", &intrusion.notify)?;
+    }
+
+    if let Some(auth) = &self.auth {
+      let mut names = HashSet::new();
+      let mut hashes = HashSet::new();
+
+      for (i, token) in auth.tokens.iter().enumerate() {
+        let key = format!("auth.tokens[{i}]");
+
+        if !names.insert(token.name.as_str()) {
+          return Err(invalid(format!("{key}.name"), format!("duplicate token name `{}`", token.name)))
+        }
+
+        if token.hash.len() != 64 || !token.hash.chars().all(|c| c.is_ascii_hexdigit()) {
+          return Err(invalid(format!("{key}.hash"), "must be a hex-encoded SHA-256 hash"))
+        }
+
+        if !hashes.insert(token.hash.to_ascii_lowercase()) {
+          return Err(invalid(format!("{key}.hash"), "duplicate token hash"))
+        }
+
+        if let Some(doors) = &token.doors {
+          if token.role == Role::Admin {
+            return Err(invalid(format!("{key}.doors"), "cannot restrict the doors of an admin"))
+          }
+
+          for (j, door) in doors.iter().enumerate() {
+            if self.door(door).is_none() {
+              return Err(invalid(format!("{key}.doors[{j}]"), format!("unknown door `{door}`")))
+            }
+          }
+        }
+      }
+    }
+
+    if let Some(tls) = &self.tls
+      && tls.client_ca.is_some()
+      && self.auth.is_none()
+    {
+      return Err(invalid("tls.client_ca", "requires `auth`"))
+    }
+
+    if let Some(mqtt) = &self.mqtt {
+      if mqtt.host.is_empty() {
+        return Err(invalid("mqtt.host", "must not be empty"))
+      }
+
+      if mqtt.prefix.is_empty() || mqtt.prefix.contains(['+', '#']) || mqtt.prefix.ends_with('/') {
+        return Err(invalid("mqtt.prefix", "must be a non-empty topic without wildcards or trailing `/`"))
+      }
+
+      if mqtt.discovery_prefix.is_empty()
+        || mqtt.discovery_prefix.contains(['+', '#'])
+        || mqtt.discovery_prefix.ends_with('/')
+      {
+        return Err(invalid("mqtt.discovery_prefix", "must be a non-empty topic without wildcards or trailing `/`"))
+      }
+
+      if mqtt.password.is_some() && mqtt.username.is_none() {
+        return Err(invalid("mqtt.password", "requires `mqtt.username`"))
+      }
+
+      for (i, door) in self.doors.iter().enumerate() {
+        if door.id.contains(['/', '+', '#']) {
+          return Err(invalid(format!("doors[{i}].id"), "must not contain `/`, `+` or `#` when using MQTT"))
+        }
+      }
+    }
+
+    Ok(())
+  }
+}
+
+fn validate_notify(key: &str, notify: &[NotifyConfig]) -> Result<(), ConfigError> {
+  for (i, notify) in notify.iter().enumerate() {
+    match notify {
+      NotifyConfig::Command { command } => {
+        if command.is_empty() {
+          return Err(invalid(format!("{key}[{i}].command"), "must not be empty"))
+        }
+      },
+    }
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const DEFAULT_CONFIG: &str = include_str!("../door-server.toml");
+
+  /// Parse the default configuration with `from` replaced by `to`, returning the key of the validation error.
+  fn invalid_key(from: &str, to: &str) -> String {
+    assert!(DEFAULT_CONFIG.contains(from), "default configuration does not contain `{from}`");
+
+    match DEFAULT_CONFIG.replacen(from, to, 1).parse::<Config>() {
+      Err(ConfigError::Invalid { key, .. }) => key,
+      Err(err) => panic!("unexpected error: {err}"),
+      Ok(_) => panic!("configuration with `{to}` is valid"),
+    }
+  }
+
+  #[test]
+  fn default_config_is_valid() {
+    let config = DEFAULT_CONFIG.parse::<Config>().unwrap();
+    assert_eq!(config.doors.len(), 4);
+  }
+
+  #[test]
+  fn duplicate_door_id() {
+    assert_eq!(invalid_key(r#"id = "cellar-door-1""#, r#"id = "main-door-1""#), "doors[1].id");
+  }
+
+  #[test]
+  fn duplicate_segment() {
+    assert_eq!(invalid_key(r#"segment = "bottom-right""#, r#"segment = "top-left""#), "doors[1].segment");
+  }
+
+  #[test]
+  fn duplicate_pin() {
+    assert_eq!(
+      invalid_key("pins = { open = 26, contact = 1 }", "pins = { open = 19, contact = 1 }"),
+      "doors[1].pins.open"
+    );
+    assert_eq!(invalid_key("close = 5, contact = 2 }", "close = 5, contact = 25 }"), "doors[3].pins.contact");
+    assert_eq!(invalid_key("pin = 24", "pin = 17"), "buttons[1].pin");
+    assert_eq!(invalid_key("red = 23", "red = 0"), "buttons[1].led.red");
+  }
+
+  #[test]
+  fn garage_pins_are_required() {
+    assert_eq!(invalid_key("stop = 20, ", ""), "doors[2].pins.stop");
+  }
+}
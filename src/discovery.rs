@@ -0,0 +1,108 @@
+use std::collections::BTreeSet;
+
+use serde_json::json;
+
+use door_server::config::{ButtonType, Config, DoorType, MqttConfig};
+
+/// Replace characters which are not allowed in node and object IDs of discovery topics.
+fn object_id(id: &str) -> String {
+  id.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' }).collect()
+}
+
+/// Map `unknown` to `None`, which resets the state in Home Assistant.
+const STATE_TEMPLATE: &str = "{{ 'None' if value == 'unknown' else value }}";
+
+/// Home Assistant discovery configs for all doors, their bells and their finger scanners, as topics and payloads.
+///
+/// Entities and devices are identified by door ID, so renaming a door updates them instead of adding new ones.
+pub fn configs(config: &Config, mqtt: &MqttConfig) -> Vec<(String, serde_json::Value)> {
+  let discovery_prefix = &mqtt.discovery_prefix;
+  let node = object_id(&mqtt.client_id);
+  let availability_topic = format!("{}/status", mqtt.prefix);
+
+  let mut configs = Vec::new();
+
+  for door in &config.doors {
+    let object = object_id(&door.id);
+    let unique_id = format!("{node}_{object}");
+    let topic = format!("{}/{}", mqtt.prefix, door.id);
+
+    let lock = json!({
+      "state_topic": format!("{topic}/lock"),
+      "value_template": STATE_TEMPLATE,
+      "state_locked": "locked",
+      "state_unlocked": "unlocked",
+      "state_jammed": "jammed",
+      "payload_lock": "lock",
+      "payload_unlock": "unlock",
+    });
+
+    let (component, model, entity) = match door.ty {
+      DoorType::Door => ("lock", "Electric Strike", lock),
+      DoorType::MotorLock => ("lock", "Motor Lock", lock),
+      DoorType::Garage => (
+        "cover",
+        "Garage Door",
+        json!({
+          "device_class": "garage",
+          "state_topic": format!("{topic}/motion"),
+          "value_template": STATE_TEMPLATE,
+          "payload_open": "unlock",
+          "payload_close": "lock",
+          "payload_stop": "stop",
+        }),
+      ),
+    };
+
+    let mut entity = entity.as_object().unwrap().to_owned();
+    entity.insert("name".to_owned(), json!(null));
+    entity.insert("unique_id".to_owned(), json!(unique_id));
+    entity.insert("command_topic".to_owned(), json!(format!("{topic}/command")));
+    entity.insert("availability_topic".to_owned(), json!(availability_topic));
+    entity.insert(
+      "device".to_owned(),
+      json!({
+        "identifiers": [unique_id],
+        "name": door.name,
+        "model": model,
+      }),
+    );
+    configs.push((format!("{discovery_prefix}/{component}/{node}/{object}/config"), entity.into()));
+
+    let has_bell = config.buttons.iter().any(|button| button.ty == ButtonType::Bell && button.door == door.id);
+    if has_bell {
+      configs.push((
+        format!("{discovery_prefix}/event/{node}/{object}_bell/config"),
+        json!({
+          "name": "Bell",
+          "unique_id": format!("{unique_id}_bell"),
+          "device_class": "doorbell",
+          "state_topic": format!("{topic}/event/bell"),
+          "value_template": r#"{"event_type": "ring"}"#,
+          "event_types": ["ring"],
+          "availability_topic": availability_topic,
+          "device": { "identifiers": [unique_id] },
+        }),
+      ));
+    }
+
+    // Only successful scans trigger, with the user name in `trigger.payload_json.user_name`.
+    let scanners = config.ekey.scanners.iter().filter(|(_, scanner_door)| **scanner_door == door.id);
+    for scanner in scanners.map(|(scanner, _)| scanner).collect::<BTreeSet<_>>() {
+      configs.push((
+        format!("{discovery_prefix}/device_automation/{node}/{object}_{}/config", object_id(scanner)),
+        json!({
+          "automation_type": "trigger",
+          "topic": format!("{topic}/event/finger_scan"),
+          "type": "finger_scan",
+          "subtype": scanner,
+          "value_template": "{{ value_json.finger_scanner_name if value_json.action == 'open' else '' }}",
+          "payload": scanner,
+          "device": { "identifiers": [unique_id] },
+        }),
+      ));
+    }
+  }
+
+  configs
+}
@@ -1,42 +1,81 @@
 use std::time::Duration;
 
-use actix_rt::time::sleep;
-use rppal::gpio::{Bias, InputPin, IoPin, Mode, Trigger};
-
 use super::*;
+use crate::hal::{self, InputPin, TriggerPin};
 
 #[derive(Debug)]
 pub struct Door {
-  trigger_open: IoPin,
-  contact: InputPin,
+  trigger_open: Box<dyn TriggerPin>,
+  contact: Box<dyn InputPin>,
+  pulse: Duration,
+  held: bool,
 }
 
 impl Door {
-  pub fn new(mut trigger_open: IoPin, contact: InputPin) -> Self {
-    trigger_open.set_high();
+  pub fn new(mut trigger_open: Box<dyn TriggerPin>, contact: Box<dyn InputPin>) -> Self {
+    // Also releases the strike in case it was held when the server stopped.
+    trigger_open.set_inactive();
+
+    Self { trigger_open, contact, pulse: hal::DEFAULT_PULSE, held: false }
+  }
+
+  /// Set the default length of the pulse releasing the strike.
+  pub fn with_pulse(mut self, pulse: Duration) -> Self {
+    self.pulse = pulse;
+    self
+  }
+
+  /// Release the strike for `pulse`, or the default pulse length if `None`.
+  pub async fn open(&mut self, pulse: Option<Duration>) {
+    if self.held {
+      return
+    }
 
-    Self { trigger_open, contact }
+    hal::pulse(&mut *self.trigger_open, pulse.unwrap_or(self.pulse)).await;
   }
 
-  pub async fn open(&mut self) {
-    self.trigger_open.set_mode(Mode::Output);
+  /// Keep the strike released until [`Door::release`] is called.
+  pub fn hold(&mut self) {
+    self.held = true;
+    self.trigger_open.set_active();
+  }
 
-    self.trigger_open.set_low();
-    sleep(Duration::from_millis(250)).await;
-    self.trigger_open.set_high();
+  pub fn release(&mut self) {
+    self.held = false;
+    self.trigger_open.set_inactive();
+  }
+}
+
+impl Actuator for Door {
+  fn capabilities(&self) -> &'static [Capability] {
+    &[Capability::Open, Capability::Hold]
+  }
+
+  fn open(&mut self, pulse: Option<Duration>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+      Door::open(self, pulse).await;
+      Ok(())
+    })
+  }
+
+  fn hold(&mut self) -> BoxFuture<'_, Result<()>> {
+    Door::hold(self);
+    Box::pin(async { Ok(()) })
+  }
 
-    self.trigger_open.set_mode(Mode::Input);
-    self.trigger_open.set_bias(Bias::PullUp);
+  fn release(&mut self) -> BoxFuture<'_, Result<()>> {
+    Door::release(self);
+    Box::pin(async { Ok(()) })
   }
 }
 
 impl StatefulDoor for Door {
-  fn on_change<C, F>(&mut self, callback: C)
+  fn on_change<C, F>(&mut self, bus: &EventBus, callback: C) -> Result<()>
   where
-    F: Future,
-    C: (FnMut(bool) -> F) + Send + 'static,
+    F: Future + 'static,
+    C: (FnMut(bool) -> F) + 'static,
   {
-    self.contact.set_async_interrupt(Trigger::Both, Some(Duration::from_millis(50)), on_change_async(callback)).unwrap()
+    self.contact.set_interrupt(Some(Duration::from_millis(50)), bus.on_change(callback))
   }
 
   fn is_closed(&self) -> bool {
@@ -47,3 +86,62 @@ impl StatefulDoor for Door {
     !self.is_closed()
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use actix_rt::time::Instant;
+
+  use super::*;
+  use crate::hal::{Level, MemoryBackend};
+
+  #[tokio::test(start_paused = true)]
+  async fn open_pulses_trigger() {
+    let backend = MemoryBackend::new();
+    let mut door = Door::new(Box::new(backend.pin(19)), Box::new(backend.pin(17)));
+    let mut trigger = backend.pin(19).subscribe();
+    assert_eq!(*trigger.borrow_and_update(), Level::High);
+
+    for (pulse, expected) in [(None, hal::DEFAULT_PULSE), (Some(Duration::from_secs(2)), Duration::from_secs(2))] {
+      let start = Instant::now();
+      let pulsed = async {
+        trigger.changed().await.unwrap();
+        assert_eq!(*trigger.borrow_and_update(), Level::Low);
+        trigger.changed().await.unwrap();
+        assert_eq!(*trigger.borrow_and_update(), Level::High);
+        start.elapsed()
+      };
+
+      let ((), elapsed) = tokio::join!(Door::open(&mut door, pulse), pulsed);
+      assert_eq!(elapsed, expected);
+    }
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn held_door_is_not_pulsed() {
+    let backend = MemoryBackend::new();
+    let mut door = Door::new(Box::new(backend.pin(19)), Box::new(backend.pin(17))).with_pulse(Duration::from_secs(1));
+
+    door.hold();
+    assert_eq!(backend.pin(19).level(), Level::Low);
+
+    let start = Instant::now();
+    Door::open(&mut door, None).await;
+    assert_eq!(start.elapsed(), Duration::ZERO);
+    assert_eq!(backend.pin(19).level(), Level::Low);
+
+    door.release();
+    assert_eq!(backend.pin(19).level(), Level::High);
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn toggle_is_unsupported() {
+    let backend = MemoryBackend::new();
+    let mut door = Door::new(Box::new(backend.pin(19)), Box::new(backend.pin(17)));
+
+    assert!(!door.supports(Capability::Toggle));
+    let err = Actuator::toggle(&mut door).await.unwrap_err();
+    assert!(matches!(err, Error::Unsupported(Capability::Toggle)));
+    assert_eq!(err.to_string(), "door does not support `toggle`");
+    assert_eq!(backend.pin(19).level(), Level::High);
+  }
+}
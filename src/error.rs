@@ -0,0 +1,69 @@
+use std::fmt;
+
+use crate::Capability;
+
+#[cfg(feature = "rppal")]
+use rppal::{gpio, spi};
+
+#[derive(Debug)]
+pub enum Error {
+  /// The GPIO peripheral could not be accessed.
+  #[cfg(feature = "rppal")]
+  Gpio(gpio::Error),
+  /// A GPIO pin could not be set up.
+  #[cfg(feature = "rppal")]
+  Pin { pin: u8, source: gpio::Error },
+  /// The LED strip could not be opened or written to.
+  #[cfg(feature = "rppal")]
+  Spi(spi::Error),
+  /// A garage door did not report being closed in time.
+  CloseFailed,
+  /// A garage door was stopped or opened again while closing.
+  Interrupted,
+  /// A garage door was still closed when it should have been open.
+  OpenFailed,
+  /// A motor lock did not reach its end position in time.
+  Jammed,
+  /// The door cannot be locked while it is open.
+  DoorOpen,
+  /// The door does not support the requested capability.
+  Unsupported(Capability),
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      #[cfg(feature = "rppal")]
+      Self::Gpio(err) => write!(f, "GPIO error: {err}"),
+      #[cfg(feature = "rppal")]
+      Self::Pin { pin, source } => write!(f, "failed to set up pin {pin}: {source}"),
+      #[cfg(feature = "rppal")]
+      Self::Spi(err) => write!(f, "SPI error: {err}"),
+      Self::CloseFailed => write!(f, "door did not close"),
+      Self::Interrupted => write!(f, "closing the door was interrupted"),
+      Self::OpenFailed => write!(f, "door did not open"),
+      Self::Jammed => write!(f, "lock is jammed"),
+      Self::DoorOpen => write!(f, "door is open"),
+      Self::Unsupported(capability) => write!(f, "door does not support `{capability}`"),
+    }
+  }
+}
+
+impl std::error::Error for Error {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      #[cfg(feature = "rppal")]
+      Self::Gpio(err) | Self::Pin { source: err, .. } => Some(err),
+      #[cfg(feature = "rppal")]
+      Self::Spi(err) => Some(err),
+      Self::CloseFailed
+      | Self::Interrupted
+      | Self::OpenFailed
+      | Self::Jammed
+      | Self::DoorOpen
+      | Self::Unsupported(_) => None,
+    }
+  }
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
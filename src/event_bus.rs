@@ -0,0 +1,226 @@
+use std::{
+  collections::VecDeque,
+  future::Future,
+  sync::{Arc, Mutex},
+};
+
+use tokio::sync::{
+  Notify,
+  mpsc::{self, error::TrySendError},
+};
+
+use crate::hal::{Interrupt, Level};
+
+/// Number of edges which can be queued on the bus or for a subscriber before they are coalesced.
+const CAPACITY: usize = 64;
+
+#[derive(Debug, Clone, Copy)]
+struct Edge {
+  subscriber: usize,
+  level: Level,
+}
+
+#[derive(Debug, Default)]
+struct Subscriber {
+  /// Latest level of edges which did not fit into the bus, delivered once the bus has drained.
+  overflow: Mutex<Option<Level>>,
+  /// Levels waiting for the subscriber's callback.
+  levels: Mutex<VecDeque<Level>>,
+  ready: Notify,
+}
+
+impl Subscriber {
+  /// Queue a level. If the subscriber is not keeping up, the newest queued level is replaced instead,
+  /// so the last level it sees is always the current one.
+  fn push(&self, level: Level) {
+    let mut levels = self.levels.lock().unwrap();
+
+    let full = levels.len() >= CAPACITY;
+    if full {
+      log::warn!("Subscriber is not keeping up, coalescing edges.");
+      levels.pop_back();
+    }
+    if !full || levels.back() != Some(&level) {
+      levels.push_back(level);
+    }
+
+    self.ready.notify_one();
+  }
+}
+
+/// Delivers interrupts from all input pins to their subscribers on the main runtime.
+///
+/// Interrupt handlers only push edges into a single bounded channel. A dispatcher task forwards them
+/// to one task per subscriber, so every subscriber sees its edges in order without blocking the others.
+/// Edges which do not fit are coalesced to the latest level per subscriber rather than dropped.
+#[derive(Debug, Clone)]
+pub struct EventBus {
+  edges: mpsc::Sender<Edge>,
+  overflowed: Arc<Notify>,
+  subscribers: Arc<Mutex<Vec<Arc<Subscriber>>>>,
+}
+
+impl EventBus {
+  /// Create a new event bus. Must be called from within the main runtime.
+  pub fn new() -> Self {
+    let (edges, receiver) = mpsc::channel(CAPACITY);
+    let overflowed = Arc::new(Notify::new());
+    let subscribers = Arc::new(Mutex::new(Vec::new()));
+
+    actix_rt::spawn(dispatch(receiver, overflowed.clone(), subscribers.clone()));
+
+    Self { edges, overflowed, subscribers }
+  }
+
+  /// Create an interrupt handler which calls `callback` with whether the pin is low, i.e. closed.
+  pub fn on_change<C, F>(&self, mut callback: C) -> Interrupt
+  where
+    F: Future + 'static,
+    C: (FnMut(bool) -> F) + 'static,
+  {
+    let subscriber = Arc::new(Subscriber::default());
+
+    let index = {
+      let mut subscribers = self.subscribers.lock().unwrap();
+      subscribers.push(subscriber.clone());
+      subscribers.len() - 1
+    };
+
+    let receiver = subscriber.clone();
+    actix_rt::spawn(async move {
+      loop {
+        let level = receiver.levels.lock().unwrap().pop_front();
+
+        match level {
+          Some(level) => {
+            callback(level == Level::Low).await;
+          },
+          None => receiver.ready.notified().await,
+        }
+      }
+    });
+
+    let edges = self.edges.clone();
+    let overflowed = self.overflowed.clone();
+    Box::new(move |level| {
+      let mut overflow = subscriber.overflow.lock().unwrap();
+
+      // Keep edges in order: once one did not fit, coalesce the following ones with it.
+      if overflow.is_some() {
+        *overflow = Some(level);
+        return
+      }
+
+      if let Err(TrySendError::Full(_)) = edges.try_send(Edge { subscriber: index, level }) {
+        log::warn!("Event bus is full, coalescing edges.");
+        *overflow = Some(level);
+        overflowed.notify_one();
+      }
+    })
+  }
+}
+
+impl Default for EventBus {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+async fn dispatch(
+  mut edges: mpsc::Receiver<Edge>,
+  overflowed: Arc<Notify>,
+  subscribers: Arc<Mutex<Vec<Arc<Subscriber>>>>,
+) {
+  loop {
+    tokio::select! {
+      biased;
+
+      edge = edges.recv() => {
+        let Some(Edge { subscriber, level }) = edge else { break };
+        let subscriber = subscribers.lock().unwrap()[subscriber].clone();
+        subscriber.push(level);
+      },
+      () = overflowed.notified() => {},
+    }
+
+    // Edges which did not fit into the bus are newer than all edges on it.
+    if edges.is_empty() {
+      for subscriber in subscribers.lock().unwrap().iter() {
+        let level = subscriber.overflow.lock().unwrap().take();
+        if let Some(level) = level {
+          subscriber.push(level);
+        }
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{cell::RefCell, rc::Rc, time::Duration};
+
+  use tokio::time::sleep;
+
+  use super::*;
+
+  /// Subscribe a callback which records the levels it is called with and takes `delay` for each.
+  fn subscribe(bus: &EventBus, delay: Duration) -> (Interrupt, Rc<RefCell<Vec<bool>>>) {
+    let closed = Rc::new(RefCell::new(Vec::new()));
+
+    let recorded = closed.clone();
+    let interrupt = bus.on_change(move |is_closed| {
+      recorded.borrow_mut().push(is_closed);
+      sleep(delay)
+    });
+
+    (interrupt, closed)
+  }
+
+  fn level(closed: bool) -> Level {
+    if closed { Level::Low } else { Level::High }
+  }
+
+  #[actix_rt::test]
+  async fn delivers_edges_in_order() {
+    tokio::time::pause();
+
+    let bus = EventBus::new();
+    let (mut interrupt, closed) = subscribe(&bus, Duration::from_millis(10));
+
+    let edges = [true, false, true, false, true];
+    for edge in edges {
+      interrupt(level(edge));
+    }
+
+    sleep(Duration::from_secs(1)).await;
+    assert_eq!(*closed.borrow(), edges);
+  }
+
+  #[actix_rt::test]
+  async fn coalesces_edges_on_overflow() {
+    tokio::time::pause();
+
+    let bus = EventBus::new();
+    let (mut slow, slow_closed) = subscribe(&bus, Duration::from_millis(10));
+    let (mut fast, fast_closed) = subscribe(&bus, Duration::ZERO);
+
+    // Far more edges than fit into the bus, ending open for the slow and closed for the fast subscriber.
+    for i in 0..=(CAPACITY * 10) {
+      slow(level(i % 2 == 1));
+      fast(level(i % 2 == 0));
+    }
+
+    sleep(Duration::from_secs(60)).await;
+
+    for (closed, last) in [(&slow_closed, false), (&fast_closed, true)] {
+      let closed = closed.borrow();
+      assert!(closed.len() <= CAPACITY + 1, "{} edges delivered", closed.len());
+      assert_eq!(closed.last(), Some(&last));
+    }
+
+    // Edges are delivered normally again once the bus has drained.
+    slow(level(true));
+    sleep(Duration::from_secs(1)).await;
+    assert_eq!(slow_closed.borrow().last(), Some(&true));
+  }
+}
@@ -1,77 +1,290 @@
 use std::time::Duration;
 
-use actix_rt::time::sleep;
-use rppal::gpio::{Bias, InputPin, IoPin, Mode, Trigger};
+use actix_rt::time::{Instant, sleep, sleep_until, timeout};
+use serde::Serialize;
+use tokio::sync::{broadcast, mpsc, watch};
 
 use super::*;
+use crate::hal::{self, InputPin, TriggerPin};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Motion {
+  Opening,
+  Closing,
+  Open,
+  Closed,
+  Stopped,
+  Unknown,
+}
+
+#[derive(Debug)]
+enum MotionEvent {
+  Open,
+  Stop,
+  Close,
+  Contact { closed: bool },
+}
 
 #[derive(Debug)]
 pub struct GarageDoor {
-  trigger_open: IoPin,  // S2 - Button OPEN (normally open)
-  trigger_stop: IoPin,  // S0 - Button STOP (normally closed)
-  trigger_close: IoPin, // S4 - Button CLOSE (normally open)
-  contact: InputPin,    //      Door Contact
+  trigger_open: Box<dyn TriggerPin>,  // S2 - Button OPEN (normally open)
+  trigger_stop: Box<dyn TriggerPin>,  // S0 - Button STOP (normally closed)
+  trigger_close: Box<dyn TriggerPin>, // S4 - Button CLOSE (normally open)
+  contact: Box<dyn InputPin>,         //      Door Contact
+  motion_events: mpsc::UnboundedSender<MotionEvent>,
+  motion: watch::Receiver<Motion>,
+  open_failed: broadcast::Sender<()>,
+  /// Notified whenever the door is stopped or opened, which ends waiting for it to close.
+  interrupted: watch::Sender<()>,
+  close_timeout: Duration,
+  pulse: Duration,
+  stop_settle: Duration,
 }
 
 impl GarageDoor {
-  pub fn new(mut trigger_open: IoPin, mut trigger_stop: IoPin, mut trigger_close: IoPin, contact: InputPin) -> Self {
-    trigger_open.set_high();
-    trigger_open.set_bias(Bias::PullUp);
-    trigger_stop.set_high();
-    trigger_stop.set_bias(Bias::PullUp);
-    trigger_close.set_high();
-    trigger_close.set_bias(Bias::PullUp);
+  pub fn new(
+    mut trigger_open: Box<dyn TriggerPin>,
+    mut trigger_stop: Box<dyn TriggerPin>,
+    mut trigger_close: Box<dyn TriggerPin>,
+    contact: Box<dyn InputPin>,
+    travel_time: Duration,
+    close_timeout: Duration,
+  ) -> Self {
+    trigger_open.set_inactive();
+    trigger_stop.set_inactive();
+    trigger_close.set_inactive();
+
+    let closed = contact.is_low();
+    let (motion_events, events) = mpsc::unbounded_channel();
+    let (motion_sender, motion) = watch::channel(if closed { Motion::Closed } else { Motion::Unknown });
+    let (open_failed, _) = broadcast::channel(1);
+    tokio::spawn(track_motion(events, motion_sender, open_failed.clone(), travel_time, closed));
+
+    Self {
+      trigger_stop,
+      trigger_open,
+      trigger_close,
+      contact,
+      motion_events,
+      motion,
+      open_failed,
+      interrupted: watch::Sender::new(()),
+      close_timeout,
+      pulse: hal::DEFAULT_PULSE,
+      stop_settle: Duration::from_millis(500),
+    }
+  }
+
+  /// Set the default length of the button pulses.
+  pub fn with_pulse(mut self, pulse: Duration) -> Self {
+    self.pulse = pulse;
+    self
+  }
+
+  /// Set how long to wait after stopping the door before moving it again.
+  pub fn with_stop_settle(mut self, stop_settle: Duration) -> Self {
+    self.stop_settle = stop_settle;
+    self
+  }
 
-    Self { trigger_stop, trigger_open, trigger_close, contact }
+  /// Watch the motion state, which is only tracked once [`StatefulDoor::on_change`] has been called.
+  pub fn motion(&self) -> watch::Receiver<Motion> {
+    self.motion.clone()
   }
 
-  pub async fn open(&mut self) {
+  /// Get notified whenever the door was still closed when it should have been open, e.g. because the opener has no
+  /// power. Its motion is reported as [`Motion::Closed`] then.
+  pub fn open_failed(&self) -> broadcast::Receiver<()> {
+    self.open_failed.subscribe()
+  }
+
+  /// Open the door with a pulse of the given length, or the default pulse length if `None`.
+  pub async fn open(&mut self, pulse: Option<Duration>) {
     if self.is_open() {
-      self.stop().await;
+      self.pulse_stop().await;
     }
 
-    self.trigger_open.set_mode(Mode::Output);
-    self.trigger_open.set_low();
-    sleep(Duration::from_millis(250)).await;
-    self.trigger_open.set_high();
-
-    self.trigger_open.set_mode(Mode::Input);
-    self.trigger_open.set_bias(Bias::PullUp);
+    hal::pulse(&mut *self.trigger_open, pulse.unwrap_or(self.pulse)).await;
+    let _ = self.motion_events.send(MotionEvent::Open);
+    self.interrupted.send_replace(());
   }
 
   pub async fn stop(&mut self) {
-    self.trigger_stop.set_mode(Mode::Output);
-    self.trigger_stop.set_low();
-    sleep(Duration::from_millis(250)).await;
-    self.trigger_stop.set_high();
-    sleep(Duration::from_millis(500)).await;
+    self.pulse_stop().await;
+    self.interrupted.send_replace(());
+  }
 
-    self.trigger_stop.set_mode(Mode::Input);
-    self.trigger_stop.set_bias(Bias::PullUp);
+  async fn pulse_stop(&mut self) {
+    hal::pulse(&mut *self.trigger_stop, self.pulse).await;
+    let _ = self.motion_events.send(MotionEvent::Stop);
+    sleep(self.stop_settle).await;
   }
 
-  pub async fn close(&mut self) {
+  /// Close the door with a pulse of the given length, or the default pulse length if `None`.
+  pub async fn close(&mut self, pulse: Option<Duration>) {
     if self.is_open() {
-      self.stop().await;
+      self.pulse_stop().await;
     }
 
-    self.trigger_close.set_mode(Mode::Output);
-    self.trigger_close.set_low();
-    sleep(Duration::from_millis(250)).await;
-    self.trigger_close.set_high();
+    hal::pulse(&mut *self.trigger_close, pulse.unwrap_or(self.pulse)).await;
+    let _ = self.motion_events.send(MotionEvent::Close);
+  }
+
+  /// Wait until the door is closed, failing if it is not closed within the close timeout or if it is stopped or
+  /// opened meanwhile.
+  pub fn closed(&self) -> impl Future<Output = Result<()>> + Send + 'static {
+    let mut motion = self.motion();
+    let mut interrupted = self.interrupted.subscribe();
+    let close_timeout = self.close_timeout;
 
-    self.trigger_close.set_mode(Mode::Input);
-    self.trigger_close.set_bias(Bias::PullUp);
+    async move {
+      tokio::select! {
+        closed = timeout(close_timeout, motion.wait_for(|motion| *motion == Motion::Closed)) => match closed {
+          Ok(Ok(_)) => Ok(()),
+          _ => Err(Error::CloseFailed),
+        },
+        _ = interrupted.changed() => Err(Error::Interrupted),
+      }
+    }
+  }
+
+  /// Close the door if it is open, otherwise open it.
+  pub async fn toggle(&mut self) {
+    if self.is_open() { self.close(None).await } else { self.open(None).await }
+  }
+}
+
+async fn track_motion(
+  mut events: mpsc::UnboundedReceiver<MotionEvent>,
+  motion: watch::Sender<Motion>,
+  open_failed: broadcast::Sender<()>,
+  travel_time: Duration,
+  mut closed: bool,
+) {
+  // Time at which the door should have reached its end position.
+  let mut deadline = None;
+
+  loop {
+    let current = *motion.borrow();
+
+    let next = tokio::select! {
+      event = events.recv() => {
+        let Some(event) = event else { return };
+
+        match event {
+          MotionEvent::Contact { closed: true } => {
+            closed = true;
+            deadline = None;
+            Motion::Closed
+          },
+          MotionEvent::Contact { closed: false } => {
+            closed = false;
+
+            // Opened by something else, e.g. a remote control.
+            if current == Motion::Closed {
+              deadline = Some(Instant::now() + travel_time);
+              Motion::Opening
+            } else {
+              current
+            }
+          },
+          MotionEvent::Open => {
+            deadline = Some(Instant::now() + travel_time);
+            Motion::Opening
+          },
+          MotionEvent::Close if closed => {
+            deadline = None;
+            Motion::Closed
+          },
+          MotionEvent::Close => {
+            deadline = Some(Instant::now() + travel_time);
+            Motion::Closing
+          },
+          MotionEvent::Stop => {
+            deadline = None;
+            if closed { Motion::Closed } else { Motion::Stopped }
+          },
+        }
+      },
+      _ = sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+        deadline = None;
+
+        match current {
+          // The contact should have been opened by now, so the opener ignored the pulse.
+          Motion::Opening if closed => {
+            let _ = open_failed.send(());
+            Motion::Closed
+          },
+          Motion::Opening => Motion::Open,
+          // The contact should have been closed by now.
+          Motion::Closing => Motion::Unknown,
+          motion => motion,
+        }
+      },
+    };
+
+    motion.send_if_modified(|motion| {
+      let changed = *motion != next;
+      *motion = next;
+      changed
+    });
+  }
+}
+
+impl Actuator for GarageDoor {
+  fn capabilities(&self) -> &'static [Capability] {
+    &[Capability::Open, Capability::Close, Capability::Stop, Capability::Toggle]
+  }
+
+  fn open(&mut self, pulse: Option<Duration>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+      GarageDoor::open(self, pulse).await;
+      Ok(())
+    })
+  }
+
+  fn close(&mut self, pulse: Option<Duration>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async move {
+      GarageDoor::close(self, pulse).await;
+      Ok(())
+    })
+  }
+
+  fn closed(&self) -> Option<BoxFuture<'static, Result<()>>> {
+    Some(Box::pin(GarageDoor::closed(self)))
+  }
+
+  fn stop(&mut self) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async {
+      GarageDoor::stop(self).await;
+      Ok(())
+    })
+  }
+
+  fn toggle(&mut self) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async {
+      GarageDoor::toggle(self).await;
+      Ok(())
+    })
   }
 }
 
 impl StatefulDoor for GarageDoor {
-  fn on_change<C, F>(&mut self, callback: C)
+  fn on_change<C, F>(&mut self, bus: &EventBus, mut callback: C) -> Result<()>
   where
-    F: Future,
-    C: (FnMut(bool) -> F) + Send + 'static,
+    F: Future + 'static,
+    C: (FnMut(bool) -> F) + 'static,
   {
-    self.contact.set_async_interrupt(Trigger::Both, Some(Duration::from_millis(50)), on_change_async(callback)).unwrap()
+    let motion_events = self.motion_events.clone();
+
+    self.contact.set_interrupt(
+      Some(Duration::from_millis(50)),
+      bus.on_change(move |closed| {
+        let _ = motion_events.send(MotionEvent::Contact { closed });
+        callback(closed)
+      }),
+    )
   }
 
   fn is_closed(&self) -> bool {
@@ -82,3 +295,209 @@ impl StatefulDoor for GarageDoor {
     !self.is_closed()
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+  };
+
+  use tokio::sync::RwLock;
+
+  use super::*;
+  use crate::{
+    close_and_wait,
+    hal::{Level, MemoryBackend},
+  };
+
+  const OPEN: u8 = 21;
+  const STOP: u8 = 20;
+  const CLOSE: u8 = 16;
+  const CONTACT: u8 = 25;
+  const TRAVEL_TIME: Duration = Duration::from_secs(20);
+  const CLOSE_TIMEOUT: Duration = Duration::from_secs(30);
+
+  fn garage_door(backend: &MemoryBackend, closed: bool) -> GarageDoor {
+    backend.pin(CONTACT).set_level(if closed { Level::Low } else { Level::High });
+
+    let mut door = GarageDoor::new(
+      Box::new(backend.pin(OPEN)),
+      Box::new(backend.pin(STOP)),
+      Box::new(backend.pin(CLOSE)),
+      Box::new(backend.pin(CONTACT)),
+      TRAVEL_TIME,
+      CLOSE_TIMEOUT,
+    );
+    door.on_change(&EventBus::new(), |_| async {}).unwrap();
+    door
+  }
+
+  /// Let the event bus deliver contact changes and the motion tracking catch up.
+  async fn settle() {
+    sleep(Duration::from_millis(1)).await;
+  }
+
+  /// Count the pulses of the close button, closing the contact on the given pulse.
+  fn count_close_pulses(backend: &MemoryBackend, close_on: Option<usize>) -> Arc<AtomicUsize> {
+    let pulses = Arc::new(AtomicUsize::new(0));
+    let mut close = backend.pin(CLOSE).subscribe();
+    let contact = backend.pin(CONTACT);
+
+    let counted = pulses.clone();
+    actix_rt::spawn(async move {
+      while close.changed().await.is_ok() {
+        if *close.borrow_and_update() == Level::Low {
+          let count = counted.fetch_add(1, Ordering::SeqCst) + 1;
+          if Some(count) == close_on {
+            contact.set_level(Level::Low);
+          }
+        }
+      }
+    });
+
+    pulses
+  }
+
+  #[actix_rt::test]
+  async fn motion_follows_contact_and_commands() {
+    tokio::time::pause();
+
+    let backend = MemoryBackend::new();
+    let mut door = garage_door(&backend, true);
+    let motion = door.motion();
+    assert_eq!(*motion.borrow(), Motion::Closed);
+
+    GarageDoor::open(&mut door, None).await;
+
+    settle().await;
+    assert_eq!(*motion.borrow(), Motion::Opening);
+
+    backend.pin(CONTACT).set_level(Level::High);
+    settle().await;
+    assert_eq!(*motion.borrow(), Motion::Opening);
+
+    sleep(TRAVEL_TIME).await;
+    assert_eq!(*motion.borrow(), Motion::Open);
+
+    GarageDoor::close(&mut door, None).await;
+
+    settle().await;
+    assert_eq!(*motion.borrow(), Motion::Closing);
+
+    GarageDoor::stop(&mut door).await;
+
+    settle().await;
+    assert_eq!(*motion.borrow(), Motion::Stopped);
+
+    GarageDoor::close(&mut door, None).await;
+
+    settle().await;
+    sleep(TRAVEL_TIME).await;
+    assert_eq!(*motion.borrow(), Motion::Unknown);
+
+    backend.pin(CONTACT).set_level(Level::Low);
+    settle().await;
+    assert_eq!(*motion.borrow(), Motion::Closed);
+
+    // Opened by a remote control.
+    backend.pin(CONTACT).set_level(Level::High);
+    settle().await;
+    assert_eq!(*motion.borrow(), Motion::Opening);
+  }
+
+  #[actix_rt::test]
+  async fn open_fails_if_contact_stays_closed() {
+    tokio::time::pause();
+
+    let backend = MemoryBackend::new();
+    let mut door = garage_door(&backend, true);
+    let motion = door.motion();
+    let mut open_failed = door.open_failed();
+
+    GarageDoor::open(&mut door, None).await;
+
+    settle().await;
+    assert_eq!(*motion.borrow(), Motion::Opening);
+
+    sleep(TRAVEL_TIME).await;
+    assert_eq!(*motion.borrow(), Motion::Closed);
+    assert!(open_failed.try_recv().is_ok());
+  }
+
+  /// Share a door like the server does, so it can be actuated while it is closing.
+  fn shared(door: GarageDoor) -> RwLock<Box<dyn Actuator>> {
+    RwLock::new(Box::new(door))
+  }
+
+  #[actix_rt::test]
+  async fn close_and_wait_retries_once() {
+    tokio::time::pause();
+
+    let backend = MemoryBackend::new();
+    let door = garage_door(&backend, false);
+    let motion = door.motion();
+    let pulses = count_close_pulses(&backend, Some(2));
+
+    close_and_wait(&shared(door), None).await.unwrap();
+    assert_eq!(pulses.load(Ordering::SeqCst), 2);
+    assert_eq!(*motion.borrow(), Motion::Closed);
+  }
+
+  #[actix_rt::test]
+  async fn close_and_wait_fails_after_retry() {
+    tokio::time::pause();
+
+    let backend = MemoryBackend::new();
+    let door = garage_door(&backend, false);
+    let pulses = count_close_pulses(&backend, None);
+
+    assert!(matches!(close_and_wait(&shared(door), None).await, Err(Error::CloseFailed)));
+    assert_eq!(pulses.load(Ordering::SeqCst), 2);
+  }
+
+  #[actix_rt::test]
+  async fn stop_while_closing() {
+    tokio::time::pause();
+
+    let backend = MemoryBackend::new();
+    let door = garage_door(&backend, false);
+    let motion = door.motion();
+    let pulses = count_close_pulses(&backend, None);
+    let door = shared(door);
+
+    let stop = async {
+      sleep(Duration::from_secs(5)).await;
+      assert_eq!(*motion.borrow(), Motion::Closing);
+
+      let start = Instant::now();
+      door.write().await.stop().await.unwrap();
+      assert!(start.elapsed() < Duration::from_secs(1));
+    };
+
+    let (closed, ()) = tokio::join!(close_and_wait(&door, None), stop);
+    assert!(matches!(closed, Err(Error::Interrupted)));
+    assert_eq!(pulses.load(Ordering::SeqCst), 1);
+
+    settle().await;
+    assert_eq!(*motion.borrow(), Motion::Stopped);
+  }
+
+  #[actix_rt::test]
+  async fn open_while_closing() {
+    tokio::time::pause();
+
+    let backend = MemoryBackend::new();
+    let door = shared(garage_door(&backend, false));
+    let pulses = count_close_pulses(&backend, None);
+
+    let open = async {
+      sleep(Duration::from_secs(5)).await;
+      door.write().await.open(None).await.unwrap();
+    };
+
+    let (closed, ()) = tokio::join!(close_and_wait(&door, None), open);
+    assert!(matches!(closed, Err(Error::Interrupted)));
+    assert_eq!(pulses.load(Ordering::SeqCst), 1);
+  }
+}
@@ -0,0 +1,586 @@
+use std::{
+  collections::HashMap,
+  path::{Path, PathBuf},
+  sync::{Arc, RwLock},
+  time::Duration,
+};
+
+use actix_web::{HttpRequest, HttpResponse, http::header::ContentType, web};
+use chrono::{DateTime, Datelike, Local, NaiveTime, Utc, Weekday};
+use rusqlite::{Connection, OptionalExtension, Row};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use webthing::{BaseEvent, Thing, server::ActionGenerator};
+
+use crate::{action, audit};
+
+const SCHEMA: &str = "
+  CREATE TABLE IF NOT EXISTS grants (
+    id INTEGER PRIMARY KEY,
+    hash TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    doors TEXT NOT NULL,
+    valid_from INTEGER NOT NULL,
+    valid_until INTEGER NOT NULL,
+    schedule TEXT,
+    max_uses INTEGER,
+    uses INTEGER NOT NULL DEFAULT 0
+  );
+  CREATE TABLE IF NOT EXISTS uses (
+    id INTEGER PRIMARY KEY,
+    grant_id INTEGER NOT NULL,
+    time INTEGER NOT NULL,
+    door TEXT NOT NULL
+  );
+";
+
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Number of random bytes in the token of a guest access link.
+const TOKEN_LENGTH: usize = 32;
+
+const GRANT_COLUMNS: &str = "id, name, doors, valid_from, valid_until, schedule, max_uses, uses";
+
+mod time_of_day {
+  use super::*;
+
+  pub fn serialize<S: Serializer>(time: &NaiveTime, serializer: S) -> Result<S::Ok, S::Error> {
+    time.format("%H:%M").to_string().serialize(serializer)
+  }
+
+  pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveTime, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    NaiveTime::parse_from_str(&s, "%H:%M")
+      .map_err(|_| de::Error::custom(format!("invalid time of day `{s}`, expected HH:MM")))
+  }
+}
+
+fn all_days() -> Vec<Weekday> {
+  vec![Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri, Weekday::Sat, Weekday::Sun]
+}
+
+/// Days and local times of day at which a grant can be used.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Schedule {
+  #[serde(default = "all_days")]
+  days: Vec<Weekday>,
+  #[serde(with = "time_of_day")]
+  start: NaiveTime,
+  #[serde(with = "time_of_day")]
+  end: NaiveTime,
+}
+
+impl Schedule {
+  fn contains(&self, time: DateTime<Local>) -> bool {
+    self.days.contains(&time.weekday()) && (self.start..self.end).contains(&time.time())
+  }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NewGrant {
+  name: String,
+  doors: Vec<String>,
+  #[serde(default = "Utc::now")]
+  from: DateTime<Utc>,
+  until: DateTime<Utc>,
+  schedule: Option<Schedule>,
+  max_uses: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+struct Grant {
+  id: i64,
+  name: String,
+  doors: Vec<String>,
+  from: DateTime<Utc>,
+  until: DateTime<Utc>,
+  schedule: Option<Schedule>,
+  max_uses: Option<u32>,
+  uses: u32,
+  /// Only known right after creating the grant.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  url: Option<String>,
+}
+
+impl Grant {
+  fn from_row(row: &Row<'_>) -> rusqlite::Result<Self> {
+    let doors: serde_json::Value = row.get(2)?;
+    let schedule: Option<serde_json::Value> = row.get(5)?;
+
+    Ok(Self {
+      id: row.get(0)?,
+      name: row.get(1)?,
+      doors: serde_json::from_value(doors).unwrap_or_default(),
+      from: DateTime::from_timestamp_millis(row.get(3)?).unwrap_or_default(),
+      until: DateTime::from_timestamp_millis(row.get(4)?).unwrap_or_default(),
+      schedule: schedule.and_then(|schedule| serde_json::from_value(schedule).ok()),
+      max_uses: row.get(6)?,
+      uses: row.get(7)?,
+      url: None,
+    })
+  }
+
+  /// Check whether the grant can be used for the given door now, except for the number of uses.
+  fn check(&self, door: &str, now: DateTime<Utc>) -> Result<(), &'static str> {
+    if !self.doors.iter().any(|d| d == door) {
+      return Err("This link is not valid for this door.")
+    }
+
+    if now < self.from || now >= self.until {
+      return Err("This link is not valid at this time.")
+    }
+
+    if let Some(schedule) = &self.schedule
+      && !schedule.contains(now.with_timezone(&Local))
+    {
+      return Err("This link is not valid at this time of day.")
+    }
+
+    Ok(())
+  }
+}
+
+fn hash(token: &str) -> String {
+  hex::encode(Sha256::digest(token))
+}
+
+fn escape(s: &str) -> String {
+  s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\'', "&#39;")
+}
+
+/// Time-limited guest access links, each allowing to unlock a set of doors.
+pub struct Guests {
+  path: PathBuf,
+  doors: HashMap<String, Arc<RwLock<Box<dyn Thing>>>>,
+  generator: Box<dyn ActionGenerator>,
+}
+
+impl Guests {
+  /// Add the `guest_unlock` event to a door.
+  pub fn add_event(thing: &mut dyn Thing) {
+    thing.add_available_event(
+      "guest_unlock".to_owned(),
+      json!({
+        "description": "The door has been unlocked with a guest access link.",
+        "type": "object",
+      })
+      .as_object()
+      .unwrap()
+      .to_owned(),
+    );
+  }
+
+  pub fn open(
+    path: &Path,
+    doors: HashMap<String, Arc<RwLock<Box<dyn Thing>>>>,
+    generator: Box<dyn ActionGenerator>,
+  ) -> rusqlite::Result<Self> {
+    let connection = Connection::open(path)?;
+    connection.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))?;
+    connection.execute_batch(SCHEMA)?;
+
+    Ok(Self { path: path.to_owned(), doors, generator })
+  }
+
+  fn connection(&self) -> rusqlite::Result<Connection> {
+    let connection = Connection::open(&self.path)?;
+    connection.busy_timeout(BUSY_TIMEOUT)?;
+    Ok(connection)
+  }
+
+  fn validate(&self, grant: &NewGrant) -> Result<(), String> {
+    if grant.name.is_empty() {
+      return Err("`name` must not be empty".to_owned())
+    }
+
+    if grant.doors.is_empty() {
+      return Err("`doors` must not be empty".to_owned())
+    }
+
+    if let Some(door) = grant.doors.iter().find(|door| !self.doors.contains_key(*door)) {
+      return Err(format!("unknown door `{door}`"))
+    }
+
+    if grant.until <= grant.from {
+      return Err("`until` must be after `from`".to_owned())
+    }
+
+    if let Some(schedule) = &grant.schedule
+      && (schedule.days.is_empty() || schedule.start >= schedule.end)
+    {
+      return Err("`schedule` must contain days and `start` must be before `end`".to_owned())
+    }
+
+    if grant.max_uses == Some(0) {
+      return Err("`max_uses` must not be zero".to_owned())
+    }
+
+    Ok(())
+  }
+
+  fn create(&self, grant: NewGrant, token: &str) -> rusqlite::Result<Grant> {
+    let connection = self.connection()?;
+    connection.execute(
+      "INSERT INTO grants (hash, name, doors, valid_from, valid_until, schedule, max_uses) VALUES (?, ?, ?, ?, ?, ?, ?)",
+      (
+        hash(token),
+        &grant.name,
+        json!(grant.doors),
+        grant.from.timestamp_millis(),
+        grant.until.timestamp_millis(),
+        grant.schedule.as_ref().map(|schedule| json!(schedule)),
+        grant.max_uses,
+      ),
+    )?;
+
+    Ok(Grant {
+      id: connection.last_insert_rowid(),
+      name: grant.name,
+      doors: grant.doors,
+      from: grant.from,
+      until: grant.until,
+      schedule: grant.schedule,
+      max_uses: grant.max_uses,
+      uses: 0,
+      url: None,
+    })
+  }
+
+  fn list(&self) -> rusqlite::Result<Vec<Grant>> {
+    let connection = self.connection()?;
+    let mut statement = connection.prepare(&format!("SELECT {GRANT_COLUMNS} FROM grants ORDER BY id"))?;
+    statement.query_map((), Grant::from_row)?.collect()
+  }
+
+  fn revoke(&self, id: i64) -> rusqlite::Result<bool> {
+    Ok(self.connection()?.execute("DELETE FROM grants WHERE id = ?", [id])? > 0)
+  }
+
+  fn find(&self, token: &str) -> rusqlite::Result<Option<Grant>> {
+    let connection = self.connection()?;
+    connection
+      .query_row(&format!("SELECT {GRANT_COLUMNS} FROM grants WHERE hash = ?"), [hash(token)], Grant::from_row)
+      .optional()
+  }
+
+  /// Count a use of the grant and record it, unless it has no uses left.
+  fn record_use(&self, grant: &Grant, door: &str, now: DateTime<Utc>) -> rusqlite::Result<bool> {
+    let mut connection = self.connection()?;
+    let transaction = connection.transaction()?;
+
+    let counted = transaction.execute(
+      "UPDATE grants SET uses = uses + 1 WHERE id = ? AND (max_uses IS NULL OR uses < max_uses)",
+      [grant.id],
+    )? > 0;
+    if counted {
+      transaction.execute(
+        "INSERT INTO uses (grant_id, time, door) VALUES (?, ?, ?)",
+        (grant.id, now.timestamp_millis(), door),
+      )?;
+    }
+
+    transaction.commit()?;
+    Ok(counted)
+  }
+}
+
+/// Run a blocking database operation, logging errors.
+async fn block<T: Send + 'static>(
+  guests: &web::Data<Guests>,
+  f: impl FnOnce(&Guests) -> rusqlite::Result<T> + Send + 'static,
+) -> Result<T, HttpResponse> {
+  let guests = guests.clone().into_inner();
+
+  match web::block(move || f(&guests)).await {
+    Ok(Ok(value)) => Ok(value),
+    Ok(Err(err)) => {
+      log::error!("Accessing guest grants failed: {err}");
+      Err(HttpResponse::InternalServerError().finish())
+    },
+    Err(err) => {
+      log::error!("Accessing guest grants failed: {err}");
+      Err(HttpResponse::InternalServerError().finish())
+    },
+  }
+}
+
+async fn handle_post_guests(req: HttpRequest, guests: web::Data<Guests>, grant: web::Json<NewGrant>) -> HttpResponse {
+  let grant = grant.into_inner();
+  if let Err(err) = guests.validate(&grant) {
+    return HttpResponse::BadRequest().body(err)
+  }
+
+  let mut token = [0; TOKEN_LENGTH];
+  if let Err(err) = getrandom::fill(&mut token) {
+    log::error!("Generating guest token failed: {err}");
+    return HttpResponse::InternalServerError().finish()
+  }
+  let token = hex::encode(token);
+
+  let url = {
+    let connection = req.connection_info();
+    format!("{}://{}/guest/{token}", connection.scheme(), connection.host())
+  };
+
+  match block(&guests, move |guests| guests.create(grant, &token)).await {
+    Ok(grant) => {
+      log::info!("Created guest grant {} for {}.", grant.id, grant.name);
+      HttpResponse::Created().json(Grant { url: Some(url), ..grant })
+    },
+    Err(response) => response,
+  }
+}
+
+async fn handle_get_guests(guests: web::Data<Guests>) -> HttpResponse {
+  match block(&guests, Guests::list).await {
+    Ok(grants) => HttpResponse::Ok().json(grants),
+    Err(response) => response,
+  }
+}
+
+async fn handle_delete_guest(guests: web::Data<Guests>, id: web::Path<i64>) -> HttpResponse {
+  let id = id.into_inner();
+
+  match block(&guests, move |guests| guests.revoke(id)).await {
+    Ok(true) => {
+      log::info!("Revoked guest grant {id}.");
+      HttpResponse::NoContent().finish()
+    },
+    Ok(false) => HttpResponse::NotFound().finish(),
+    Err(response) => response,
+  }
+}
+
+async fn handle_get_guest(guests: web::Data<Guests>, token: web::Path<String>) -> HttpResponse {
+  let token = token.into_inner();
+  let grant = match block(&guests, move |guests| guests.find(&token)).await {
+    Ok(Some(grant)) => grant,
+    Ok(None) => return HttpResponse::NotFound().body("This link is not valid."),
+    Err(response) => return response,
+  };
+
+  let buttons = grant
+    .doors
+    .iter()
+    .filter_map(|door| {
+      let title = guests.doors.get(door)?.read().unwrap().get_title();
+      Some(format!(r#"<p><button data-door="{}">Unlock {}</button></p>"#, escape(door), escape(&title)))
+    })
+    .collect::<String>();
+
+  let page = format!(
+    r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Door Access</title>
+<style>body {{ font-family: sans-serif; margin: 2em; }} button {{ font-size: 1.5em; padding: 0.5em 1em; }}</style>
+</head>
+<body>
+<h1>Welcome, {name}</h1>
+<p>Valid until {until}.</p>
+{buttons}
+<p id="status"></p>
+<script>
+for (const button of document.querySelectorAll("button")) {{
+  button.addEventListener("click", async () => {{
+    const response = await fetch(location.pathname + "/" + encodeURIComponent(button.dataset.door), {{ method: "POST" }});
+    document.getElementById("status").textContent = response.ok ? "Unlocked." : await response.text();
+  }});
+}}
+</script>
+</body>
+</html>
+"#,
+    name = escape(&grant.name),
+    until = grant.until.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+  );
+
+  HttpResponse::Ok().content_type(ContentType::html()).body(page)
+}
+
+async fn handle_post_guest_unlock(guests: web::Data<Guests>, path: web::Path<(String, String)>) -> HttpResponse {
+  let (token, door) = path.into_inner();
+  let now = Utc::now();
+
+  let grant = match block(&guests, move |guests| guests.find(&token)).await {
+    Ok(Some(grant)) => grant,
+    Ok(None) => return HttpResponse::NotFound().body("This link is not valid."),
+    Err(response) => return response,
+  };
+
+  let Some(thing) = guests.doors.get(&door).cloned() else { return HttpResponse::NotFound().finish() };
+  let title = thing.read().unwrap().get_title();
+
+  if let Err(reason) = grant.check(&door, now) {
+    log::warn!("Rejected guest unlock of {title} by {}: {reason}", grant.name);
+    return HttpResponse::Forbidden().body(reason)
+  }
+
+  let (id, name) = (grant.id, grant.name.clone());
+  let recorded_door = door.clone();
+  match block(&guests, move |guests| guests.record_use(&grant, &recorded_door, now)).await {
+    Ok(true) => (),
+    Ok(false) => {
+      let reason = "This link has been used up.";
+      log::warn!("Rejected guest unlock of {title} by {name}: {reason}");
+      return HttpResponse::Forbidden().body(reason)
+    },
+    Err(response) => return response,
+  }
+
+  log::info!("{title} unlocked by guest {name}.");
+  let event = BaseEvent::new("guest_unlock".to_owned(), Some(json!({ "grant": id, "name": name })));
+  thing.write().unwrap().add_event(Box::new(event));
+
+  let result = audit::sync_scope(format!("guest:{name} ({id})"), || {
+    action::request(guests.generator.as_ref(), &thing, "unlock", None)
+  });
+  match result {
+    Ok(()) => HttpResponse::NoContent().finish(),
+    Err(err) => {
+      log::error!("Guest unlock of {title} failed: {err}");
+      HttpResponse::InternalServerError().finish()
+    },
+  }
+}
+
+/// Add the `/guests` endpoints for managing grants and the `/guest/{token}` pages for using them.
+pub fn configure(config: &mut web::ServiceConfig, guests: web::Data<Guests>) {
+  config
+    .app_data(guests)
+    .service(web::resource("/guests").route(web::get().to(handle_get_guests)).route(web::post().to(handle_post_guests)))
+    .service(web::resource("/guests/{id}").route(web::delete().to(handle_delete_guest)))
+    .service(web::resource("/guest/{token}").route(web::get().to(handle_get_guest)))
+    .service(web::resource("/guest/{token}/{door}").route(web::post().to(handle_post_guest_unlock)));
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{env, fs, sync::Weak};
+
+  use chrono::{Duration, TimeZone};
+  use webthing::{Action, BaseThing};
+
+  use super::*;
+
+  struct NoActions;
+
+  impl ActionGenerator for NoActions {
+    fn generate(
+      &self,
+      _thing: Weak<RwLock<Box<dyn Thing>>>,
+      _name: String,
+      _input: Option<&serde_json::Value>,
+    ) -> Option<Box<dyn Action>> {
+      None
+    }
+  }
+
+  fn guests(name: &str) -> Guests {
+    let path = env::temp_dir().join(format!("door-server-guests-{}-{name}.sqlite", std::process::id()));
+    let _ = fs::remove_file(&path);
+
+    let doors = ["main-door-1", "cellar-door-1"]
+      .into_iter()
+      .map(|id| {
+        let thing: Box<dyn Thing> = Box::new(BaseThing::new(id.to_owned(), id.to_owned(), None, None));
+        (id.to_owned(), Arc::new(RwLock::new(thing)))
+      })
+      .collect();
+
+    Guests::open(&path, doors, Box::new(NoActions)).unwrap()
+  }
+
+  fn local(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+    Local.with_ymd_and_hms(2026, 10, day, hour, minute, 0).unwrap().with_timezone(&Utc)
+  }
+
+  fn new_grant(schedule: Option<Schedule>, max_uses: Option<u32>) -> NewGrant {
+    NewGrant {
+      name: "Cleaner".to_owned(),
+      doors: vec!["main-door-1".to_owned()],
+      from: local(19, 0, 0),
+      until: local(26, 0, 0),
+      schedule,
+      max_uses,
+    }
+  }
+
+  #[test]
+  fn validate() {
+    let guests = guests("validate");
+    assert_eq!(guests.validate(&new_grant(None, None)), Ok(()));
+
+    let mut grant = new_grant(None, None);
+    grant.doors = vec!["garage-door-1".to_owned()];
+    assert_eq!(guests.validate(&grant), Err("unknown door `garage-door-1`".to_owned()));
+
+    let mut grant = new_grant(None, None);
+    grant.until = grant.from;
+    assert!(guests.validate(&grant).is_err());
+
+    let schedule =
+      Schedule { days: all_days(), start: NaiveTime::from_hms_opt(17, 0, 0).unwrap(), end: NaiveTime::MIN };
+    assert!(guests.validate(&new_grant(Some(schedule), None)).is_err());
+    assert!(guests.validate(&new_grant(None, Some(0))).is_err());
+  }
+
+  #[test]
+  fn schedule() {
+    let guests = guests("schedule");
+
+    let schedule = Schedule {
+      days: vec![Weekday::Mon, Weekday::Wed],
+      start: NaiveTime::from_hms_opt(8, 0, 0).unwrap(),
+      end: NaiveTime::from_hms_opt(12, 30, 0).unwrap(),
+    };
+    guests.create(new_grant(Some(schedule), None), "token").unwrap();
+    let grant = guests.find("token").unwrap().unwrap();
+    assert!(guests.find("other").unwrap().is_none());
+
+    // Monday
+    assert_eq!(grant.check("main-door-1", local(19, 8, 0)), Ok(()));
+    assert_eq!(grant.check("main-door-1", local(19, 12, 29)), Ok(()));
+    assert_eq!(grant.check("main-door-1", local(19, 12, 30)), Err("This link is not valid at this time of day."));
+    assert_eq!(grant.check("main-door-1", local(19, 7, 59)), Err("This link is not valid at this time of day."));
+    assert_eq!(grant.check("cellar-door-1", local(19, 9, 0)), Err("This link is not valid for this door."));
+    // Tuesday
+    assert_eq!(grant.check("main-door-1", local(20, 9, 0)), Err("This link is not valid at this time of day."));
+    // Wednesday
+    assert_eq!(grant.check("main-door-1", local(21, 9, 0)), Ok(()));
+    // Outside of the validity period.
+    assert_eq!(
+      grant.check("main-door-1", local(19, 9, 0) - Duration::weeks(1)),
+      Err("This link is not valid at this time.")
+    );
+    assert_eq!(grant.check("main-door-1", local(26, 9, 0)), Err("This link is not valid at this time."));
+  }
+
+  #[test]
+  fn max_uses() {
+    let guests = guests("max-uses");
+
+    guests.create(new_grant(None, Some(2)), "limited").unwrap();
+    guests.create(new_grant(None, None), "unlimited").unwrap();
+
+    let limited = guests.find("limited").unwrap().unwrap();
+    assert!(guests.record_use(&limited, "main-door-1", local(19, 9, 0)).unwrap());
+    assert!(guests.record_use(&limited, "main-door-1", local(19, 10, 0)).unwrap());
+    assert!(!guests.record_use(&limited, "main-door-1", local(19, 11, 0)).unwrap());
+    assert_eq!(guests.find("limited").unwrap().unwrap().uses, 2);
+
+    let unlimited = guests.find("unlimited").unwrap().unwrap();
+    for hour in 8..12 {
+      assert!(guests.record_use(&unlimited, "main-door-1", local(19, hour, 0)).unwrap());
+    }
+    assert_eq!(guests.find("unlimited").unwrap().unwrap().uses, 4);
+
+    assert!(guests.revoke(limited.id).unwrap());
+    assert!(guests.find("limited").unwrap().is_none());
+  }
+}
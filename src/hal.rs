@@ -0,0 +1,71 @@
+use std::{fmt, time::Duration};
+
+use actix_rt::time::sleep;
+use smart_leds::RGB8;
+
+use crate::Result;
+
+mod memory;
+pub use memory::{MemoryBackend, MemoryLedStrip, MemoryPin};
+
+#[cfg(feature = "rppal")]
+mod rpi;
+#[cfg(feature = "rppal")]
+pub use rpi::RppalBackend;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+  Low,
+  High,
+}
+
+pub type Interrupt = Box<dyn FnMut(Level) + Send>;
+
+/// An input pin which can notify about edges.
+pub trait InputPin: fmt::Debug + Send + Sync {
+  fn is_low(&self) -> bool;
+
+  fn is_high(&self) -> bool {
+    !self.is_low()
+  }
+
+  /// Call `callback` with the new level whenever the level changes.
+  fn set_interrupt(&mut self, debounce: Option<Duration>, callback: Interrupt) -> Result<()>;
+}
+
+/// An open-drain output which is pulled up while inactive and pulled low while active.
+pub trait TriggerPin: fmt::Debug + Send + Sync {
+  fn set_active(&mut self);
+
+  fn set_inactive(&mut self);
+}
+
+pub trait OutputPin: fmt::Debug + Send + Sync {
+  fn set_low(&mut self);
+
+  fn set_high(&mut self);
+}
+
+pub trait LedStrip: Send {
+  fn write(&mut self, colors: &[RGB8]) -> Result<()>;
+}
+
+/// Provides pins and LED strips for a specific platform.
+pub trait Backend {
+  fn input_pin(&mut self, pin: u8) -> Result<Box<dyn InputPin>>;
+
+  fn trigger_pin(&mut self, pin: u8) -> Result<Box<dyn TriggerPin>>;
+
+  fn output_pin(&mut self, pin: u8) -> Result<Box<dyn OutputPin>>;
+
+  fn led_strip(&mut self) -> Result<Box<dyn LedStrip>>;
+}
+
+/// Default length of trigger pulses.
+pub const DEFAULT_PULSE: Duration = Duration::from_millis(250);
+
+pub(crate) async fn pulse(pin: &mut dyn TriggerPin, duration: Duration) {
+  pin.set_active();
+  sleep(duration).await;
+  pin.set_inactive();
+}
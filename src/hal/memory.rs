@@ -0,0 +1,152 @@
+use std::{
+  collections::HashMap,
+  fmt,
+  sync::{Arc, Mutex},
+  time::Duration,
+};
+
+use smart_leds::RGB8;
+use tokio::sync::watch;
+
+use super::{Backend, InputPin, Interrupt, LedStrip, Level, OutputPin, TriggerPin};
+use crate::Result;
+
+/// Backend which keeps all pin levels in memory.
+///
+/// Pins are created on first use and are pulled high by default. Handles returned by
+/// [`MemoryBackend::pin`] share their state with the pins handed out to doors, so they
+/// can be used to observe outputs and to drive inputs.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryBackend {
+  pins: Arc<Mutex<HashMap<u8, MemoryPin>>>,
+  led_strip: MemoryLedStrip,
+}
+
+impl MemoryBackend {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn pin(&self, pin: u8) -> MemoryPin {
+    self.pins.lock().unwrap().entry(pin).or_default().clone()
+  }
+
+  pub fn leds(&self) -> MemoryLedStrip {
+    self.led_strip.clone()
+  }
+}
+
+impl Backend for MemoryBackend {
+  fn input_pin(&mut self, pin: u8) -> Result<Box<dyn InputPin>> {
+    Ok(Box::new(self.pin(pin)))
+  }
+
+  fn trigger_pin(&mut self, pin: u8) -> Result<Box<dyn TriggerPin>> {
+    Ok(Box::new(self.pin(pin)))
+  }
+
+  fn output_pin(&mut self, pin: u8) -> Result<Box<dyn OutputPin>> {
+    let mut pin = self.pin(pin);
+    OutputPin::set_low(&mut pin);
+    Ok(Box::new(pin))
+  }
+
+  fn led_strip(&mut self) -> Result<Box<dyn LedStrip>> {
+    Ok(Box::new(self.leds()))
+  }
+}
+
+struct PinState {
+  level: watch::Sender<Level>,
+  interrupt: Mutex<Option<Interrupt>>,
+}
+
+#[derive(Clone)]
+pub struct MemoryPin {
+  state: Arc<PinState>,
+}
+
+impl Default for MemoryPin {
+  fn default() -> Self {
+    Self { state: Arc::new(PinState { level: watch::Sender::new(Level::High), interrupt: Mutex::new(None) }) }
+  }
+}
+
+impl fmt::Debug for MemoryPin {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("MemoryPin").field("level", &self.level()).finish()
+  }
+}
+
+impl MemoryPin {
+  pub fn level(&self) -> Level {
+    *self.state.level.borrow()
+  }
+
+  /// Set the level, calling the interrupt handler if it changed.
+  pub fn set_level(&self, level: Level) {
+    let changed = self.state.level.send_if_modified(|current| {
+      let changed = *current != level;
+      *current = level;
+      changed
+    });
+
+    if changed && let Some(interrupt) = self.state.interrupt.lock().unwrap().as_mut() {
+      interrupt(level);
+    }
+  }
+
+  /// Watch for level changes.
+  pub fn subscribe(&self) -> watch::Receiver<Level> {
+    self.state.level.subscribe()
+  }
+}
+
+impl InputPin for MemoryPin {
+  fn is_low(&self) -> bool {
+    self.level() == Level::Low
+  }
+
+  fn set_interrupt(&mut self, _debounce: Option<Duration>, callback: Interrupt) -> Result<()> {
+    *self.state.interrupt.lock().unwrap() = Some(callback);
+    Ok(())
+  }
+}
+
+impl TriggerPin for MemoryPin {
+  fn set_active(&mut self) {
+    self.set_level(Level::Low)
+  }
+
+  fn set_inactive(&mut self) {
+    self.set_level(Level::High)
+  }
+}
+
+impl OutputPin for MemoryPin {
+  fn set_low(&mut self) {
+    self.set_level(Level::Low)
+  }
+
+  fn set_high(&mut self) {
+    self.set_level(Level::High)
+  }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MemoryLedStrip {
+  colors: Arc<Mutex<Vec<RGB8>>>,
+}
+
+impl MemoryLedStrip {
+  pub fn colors(&self) -> Vec<RGB8> {
+    self.colors.lock().unwrap().clone()
+  }
+}
+
+impl LedStrip for MemoryLedStrip {
+  fn write(&mut self, colors: &[RGB8]) -> Result<()> {
+    *self.colors.lock().unwrap() = colors.to_vec();
+    Ok(())
+  }
+}
@@ -0,0 +1,94 @@
+use std::time::Duration;
+
+use rppal::{
+  gpio::{self, Bias, Event, Gpio, IoPin, Mode, Trigger},
+  spi::{Bus, Mode as SpiMode, SlaveSelect, Spi},
+};
+use smart_leds::{RGB8, SmartLedsWrite};
+use ws2812_spi::hosted::Ws2812;
+
+use super::{Backend, InputPin, Interrupt, LedStrip, Level, OutputPin, TriggerPin};
+use crate::{Error, Result};
+
+/// Backend using the GPIO and SPI peripherals of a Raspberry Pi.
+#[derive(Debug)]
+pub struct RppalBackend {
+  gpio: Gpio,
+}
+
+impl RppalBackend {
+  pub fn new() -> Result<Self> {
+    Ok(Self { gpio: Gpio::new().map_err(Error::Gpio)? })
+  }
+
+  fn pin(&self, pin: u8) -> Result<gpio::Pin> {
+    self.gpio.get(pin).map_err(|source| Error::Pin { pin, source })
+  }
+}
+
+impl Backend for RppalBackend {
+  fn input_pin(&mut self, pin: u8) -> Result<Box<dyn InputPin>> {
+    Ok(Box::new(self.pin(pin)?.into_input_pullup()))
+  }
+
+  fn trigger_pin(&mut self, pin: u8) -> Result<Box<dyn TriggerPin>> {
+    let mut io_pin = self.pin(pin)?.into_io(Mode::Input);
+    io_pin.set_bias(Bias::PullUp);
+    Ok(Box::new(io_pin))
+  }
+
+  fn output_pin(&mut self, pin: u8) -> Result<Box<dyn OutputPin>> {
+    Ok(Box::new(self.pin(pin)?.into_output_low()))
+  }
+
+  fn led_strip(&mut self) -> Result<Box<dyn LedStrip>> {
+    // On Raspberry Pi, `core_freq=250` must be set in `/boot/config.txt` in order to have a stable SPI frequency.
+    let spi_freq = 800_000 * 3;
+
+    let spi = Spi::new(Bus::Spi0, SlaveSelect::Ss0, spi_freq, SpiMode::Mode0).map_err(Error::Spi)?;
+    Ok(Box::new(Ws2812::new(spi)))
+  }
+}
+
+impl InputPin for gpio::InputPin {
+  fn is_low(&self) -> bool {
+    gpio::InputPin::is_low(self)
+  }
+
+  fn set_interrupt(&mut self, debounce: Option<Duration>, mut callback: Interrupt) -> Result<()> {
+    self
+      .set_async_interrupt(Trigger::Both, debounce, move |event: Event| {
+        callback(if event.trigger == Trigger::FallingEdge { Level::Low } else { Level::High })
+      })
+      .map_err(Error::Gpio)
+  }
+}
+
+impl TriggerPin for IoPin {
+  fn set_active(&mut self) {
+    self.set_mode(Mode::Output);
+    self.set_low();
+  }
+
+  fn set_inactive(&mut self) {
+    self.set_high();
+    self.set_mode(Mode::Input);
+    self.set_bias(Bias::PullUp);
+  }
+}
+
+impl OutputPin for gpio::OutputPin {
+  fn set_low(&mut self) {
+    gpio::OutputPin::set_low(self)
+  }
+
+  fn set_high(&mut self) {
+    gpio::OutputPin::set_high(self)
+  }
+}
+
+impl LedStrip for Ws2812<Spi> {
+  fn write(&mut self, colors: &[RGB8]) -> Result<()> {
+    SmartLedsWrite::write(self, colors.iter().cloned()).map_err(Error::Spi)
+  }
+}
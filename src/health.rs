@@ -0,0 +1,78 @@
+use std::{
+  collections::BTreeMap,
+  fmt,
+  sync::{Arc, Mutex, RwLock},
+};
+
+use serde_json::json;
+use webthing::{BaseProperty, Thing};
+
+use crate::set_property;
+
+#[derive(Default)]
+struct State {
+  failures: BTreeMap<String, String>,
+  things: Vec<Arc<RwLock<Box<dyn Thing>>>>,
+}
+
+impl State {
+  fn value(&self) -> serde_json::Value {
+    if self.failures.is_empty() {
+      return json!("ok")
+    }
+
+    json!(self.failures.iter().map(|(component, err)| format!("{component}: {err}")).collect::<Vec<_>>().join("; "))
+  }
+
+  fn notify(&self) {
+    let value = self.value();
+    for thing in &self.things {
+      set_property(thing.write().unwrap(), "health", value.clone());
+    }
+  }
+}
+
+/// Keeps track of failed components and reports them via a `health` property on all things.
+#[derive(Clone, Default)]
+pub struct Health {
+  state: Arc<Mutex<State>>,
+}
+
+impl Health {
+  pub fn add_property(&self, thing: &Arc<RwLock<Box<dyn Thing>>>) {
+    let health = json!({
+      "title": "Health",
+      "type": "string",
+      "description": "Either `ok` or a list of components which failed.",
+      "readOnly": true,
+    });
+
+    let mut state = self.state.lock().unwrap();
+    thing.write().unwrap().add_property(Box::new(BaseProperty::new(
+      "health".into(),
+      state.value(),
+      None,
+      Some(health.as_object().unwrap().to_owned()),
+    )));
+    state.things.push(thing.clone());
+  }
+
+  pub fn report(&self, component: &str, err: impl fmt::Display) {
+    let err = err.to_string();
+
+    let mut state = self.state.lock().unwrap();
+    if state.failures.get(component) != Some(&err) {
+      log::error!("{component} failed: {err}");
+      state.failures.insert(component.to_owned(), err);
+      state.notify();
+    }
+  }
+
+  pub fn resolve(&self, component: &str) {
+    let mut state = self.state.lock().unwrap();
+    if state.failures.remove(component).is_some() {
+      log::info!("{component} recovered.");
+      state.notify();
+    }
+  }
+}
@@ -0,0 +1,207 @@
+use std::{
+  path::{Path, PathBuf},
+  sync::{Arc, RwLock},
+  thread,
+};
+
+use actix_web::{HttpResponse, web};
+use chrono::{DateTime, Utc};
+use rusqlite::{
+  Connection, OpenFlags, ToSql,
+  types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, ValueRef},
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use webthing::Thing;
+
+use crate::{
+  health::Health,
+  subscription::{Kind, Message, subscribe},
+};
+
+/// ID under which the history subscribes to the message queues of things.
+const SUBSCRIBER: &str = "history";
+
+/// Door under which events of finger scanners which are not assigned to a door are recorded.
+pub const UNKNOWN_SCANNER: &str = "unknown-scanner";
+
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+
+const SCHEMA: &str = "
+  CREATE TABLE IF NOT EXISTS history (
+    id INTEGER PRIMARY KEY,
+    time INTEGER NOT NULL,
+    door TEXT NOT NULL,
+    type TEXT NOT NULL,
+    name TEXT NOT NULL,
+    value TEXT NOT NULL
+  );
+  CREATE INDEX IF NOT EXISTS history_time ON history (time);
+  CREATE INDEX IF NOT EXISTS history_door_time ON history (door, time);
+";
+
+impl ToSql for Kind {
+  fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+    Ok(self.as_str().into())
+  }
+}
+
+impl FromSql for Kind {
+  fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+    let value = value.as_str()?;
+    Kind::ALL.into_iter().find(|kind| kind.as_str() == value).ok_or(FromSqlError::InvalidType)
+  }
+}
+
+#[derive(Debug, Serialize)]
+struct Entry {
+  id: i64,
+  time: DateTime<Utc>,
+  door: String,
+  #[serde(rename = "type")]
+  kind: Kind,
+  name: String,
+  value: serde_json::Value,
+}
+
+/// Filter for querying the history, newest entries first.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Query {
+  door: Option<String>,
+  #[serde(rename = "type")]
+  kind: Option<Kind>,
+  name: Option<String>,
+  from: Option<DateTime<Utc>>,
+  to: Option<DateTime<Utc>>,
+  limit: Option<usize>,
+  #[serde(default)]
+  offset: usize,
+}
+
+/// Records property changes, actions and events of all doors in an SQLite database.
+#[derive(Clone)]
+pub struct History {
+  path: PathBuf,
+  entries: mpsc::UnboundedSender<Entry>,
+}
+
+impl History {
+  pub fn open(path: &Path, health: Health) -> rusqlite::Result<Self> {
+    let connection = Connection::open(path)?;
+    connection.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))?;
+    connection.execute_batch(SCHEMA)?;
+
+    let (entries, mut receiver) = mpsc::unbounded_channel::<Entry>();
+
+    // Writes are blocking, so do them on a separate thread.
+    thread::spawn(move || {
+      while let Some(entry) = receiver.blocking_recv() {
+        let result = connection.execute(
+          "INSERT INTO history (time, door, type, name, value) VALUES (?, ?, ?, ?, ?)",
+          (entry.time.timestamp_millis(), &entry.door, entry.kind, &entry.name, &entry.value),
+        );
+
+        match result {
+          Ok(_) => health.resolve("History"),
+          Err(err) => health.report("History", err),
+        }
+      }
+    });
+
+    Ok(Self { path: path.to_owned(), entries })
+  }
+
+  /// Record all property changes, actions and events of a door.
+  ///
+  /// Must be called after all events have been added to the thing.
+  pub fn watch(&self, door: &str, thing: &Arc<RwLock<Box<dyn Thing>>>) {
+    let mut messages = subscribe(SUBSCRIBER, thing);
+    let door = door.to_owned();
+    let entries = self.entries.clone();
+
+    actix_rt::spawn(async move {
+      while let Some(Message { time, kind, name, value }) = messages.recv().await {
+        if entries.send(Entry { id: 0, time, door: door.clone(), kind, name, value }).is_err() {
+          return
+        }
+      }
+    });
+  }
+
+  /// Record an entry which does not belong to a thing.
+  pub fn record(&self, door: &str, kind: Kind, name: &str, value: serde_json::Value) {
+    let entry = Entry { id: 0, time: Utc::now(), door: door.to_owned(), kind, name: name.to_owned(), value };
+    let _ = self.entries.send(entry);
+  }
+
+  fn query(&self, query: &Query) -> rusqlite::Result<Vec<Entry>> {
+    let connection = Connection::open_with_flags(&self.path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+
+    let from = query.from.map(|from| from.timestamp_millis());
+    let to = query.to.map(|to| to.timestamp_millis());
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as i64;
+    let offset = query.offset as i64;
+
+    let mut filters: Vec<(&str, &dyn ToSql)> = Vec::new();
+    if let Some(door) = &query.door {
+      filters.push(("door = ?", door));
+    }
+    if let Some(kind) = &query.kind {
+      filters.push(("type = ?", kind));
+    }
+    if let Some(name) = &query.name {
+      filters.push(("name = ?", name));
+    }
+    if let Some(from) = &from {
+      filters.push(("time >= ?", from));
+    }
+    if let Some(to) = &to {
+      filters.push(("time < ?", to));
+    }
+
+    let mut sql = "SELECT id, time, door, type, name, value FROM history".to_owned();
+    if !filters.is_empty() {
+      sql += " WHERE ";
+      sql += &filters.iter().map(|(filter, _)| *filter).collect::<Vec<_>>().join(" AND ");
+    }
+    sql += " ORDER BY time DESC, id DESC LIMIT ? OFFSET ?";
+
+    let params = filters.iter().map(|(_, param)| *param).chain([&limit as &dyn ToSql, &offset]);
+    let mut statement = connection.prepare(&sql)?;
+    let entries = statement.query_map(rusqlite::params_from_iter(params), |row| {
+      Ok(Entry {
+        id: row.get(0)?,
+        time: DateTime::from_timestamp_millis(row.get(1)?).unwrap_or_default(),
+        door: row.get(2)?,
+        kind: row.get(3)?,
+        name: row.get(4)?,
+        value: row.get(5)?,
+      })
+    })?;
+
+    entries.collect()
+  }
+}
+
+async fn handle_get_history(history: web::Data<History>, query: web::Query<Query>) -> HttpResponse {
+  let history = history.into_inner();
+
+  match web::block(move || history.query(&query)).await {
+    Ok(Ok(entries)) => HttpResponse::Ok().json(entries),
+    Ok(Err(err)) => {
+      log::error!("Querying history failed: {err}");
+      HttpResponse::InternalServerError().finish()
+    },
+    Err(err) => {
+      log::error!("Querying history failed: {err}");
+      HttpResponse::InternalServerError().finish()
+    },
+  }
+}
+
+/// Add the `/history` endpoint.
+pub fn configure(config: &mut web::ServiceConfig, history: History) {
+  config.app_data(web::Data::new(history)).service(web::resource("/history").route(web::get().to(handle_get_history)));
+}
@@ -0,0 +1,149 @@
+use std::{
+  error::Error,
+  sync::{Arc, RwLock},
+  time::Duration,
+};
+
+use actix_rt::time::{Instant, sleep_until};
+use serde_json::json;
+use tokio::sync::mpsc;
+use webthing::{BaseProperty, Thing};
+
+use crate::{action::SharedDoor, audit, indicator::Indicator, set_property};
+
+#[derive(Debug)]
+enum Command {
+  Hold(Option<Duration>, String),
+  Release(String),
+}
+
+/// Allows holding a door open from actions.
+#[derive(Debug, Clone)]
+pub struct HoldOpenHandle {
+  commands: mpsc::UnboundedSender<Command>,
+}
+
+impl HoldOpenHandle {
+  /// Hold the door open for `duration`, or as long as allowed if `None`.
+  pub fn hold(&self, duration: Option<Duration>) -> Result<(), Box<dyn Error>> {
+    self.commands.send(Command::Hold(duration, audit::actor())).map_err(|_| "hold open is not running".into())
+  }
+
+  pub fn release(&self) -> Result<(), Box<dyn Error>> {
+    self.commands.send(Command::Release(audit::actor())).map_err(|_| "hold open is not running".into())
+  }
+}
+
+/// Keeps a door open until it is released or its hold expires.
+pub struct HoldOpen {
+  door: SharedDoor,
+  thing: Arc<RwLock<Box<dyn Thing>>>,
+  indicator: Indicator,
+  max_duration: Duration,
+  commands: mpsc::UnboundedReceiver<Command>,
+  handle: HoldOpenHandle,
+}
+
+impl HoldOpen {
+  pub fn new(
+    door: SharedDoor,
+    thing: Arc<RwLock<Box<dyn Thing>>>,
+    indicator: Indicator,
+    max_duration: Duration,
+  ) -> Self {
+    {
+      let mut thing = thing.write().unwrap();
+
+      let hold_open = json!({
+        "title": "Hold Open",
+        "description": "Keep the door unlocked for the given duration, or until it is released.",
+        "input": {
+          "type": ["object", "null"],
+          "properties": {
+            "duration": {
+              "type": "integer",
+              "minimum": 1,
+              "maximum": max_duration.as_secs(),
+              "unit": "second",
+            },
+          },
+        },
+      });
+      thing.add_available_action("hold_open".into(), hold_open.as_object().unwrap().to_owned());
+
+      let release = json!({
+        "title": "Release",
+        "description": "Stop holding the door open.",
+      });
+      thing.add_available_action("release".into(), release.as_object().unwrap().to_owned());
+
+      let held_open = json!({
+        "title": "Held Open",
+        "type": "boolean",
+        "description": "Whether the door is currently held open.",
+        "readOnly": true,
+      });
+      thing.add_property(Box::new(BaseProperty::new(
+        "held_open".into(),
+        json!(false),
+        None,
+        Some(held_open.as_object().unwrap().to_owned()),
+      )));
+    }
+
+    let (sender, commands) = mpsc::unbounded_channel();
+
+    Self { door, thing, indicator, max_duration, commands, handle: HoldOpenHandle { commands: sender } }
+  }
+
+  pub fn handle(&self) -> HoldOpenHandle {
+    self.handle.clone()
+  }
+
+  async fn set_held_open(&self, held_open: bool) {
+    set_property(self.thing.write().unwrap(), "held_open", json!(held_open));
+    self.indicator.show_held_open(held_open).await;
+  }
+
+  pub async fn run(mut self) {
+    let name = self.thing.read().unwrap().get_title();
+    let mut until = None;
+
+    loop {
+      let command = tokio::select! {
+        command = self.commands.recv() => match command {
+          Some(command) => command,
+          None => return,
+        },
+        _ = sleep_until(until.unwrap_or_else(Instant::now)), if until.is_some() => Command::Release("hold_open".to_owned()),
+      };
+
+      match command {
+        Command::Hold(duration, actor) => {
+          let duration = duration.map_or(self.max_duration, |duration| duration.min(self.max_duration));
+
+          if until.is_none() {
+            if let Err(err) = audit::scope(actor, async { self.door.write().await.hold().await }).await {
+              log::error!("Holding {name} open failed: {err}");
+              continue
+            }
+            self.set_held_open(true).await;
+          }
+
+          log::info!("Holding {name} open for {duration:?}.");
+          until = Some(Instant::now() + duration);
+        },
+        Command::Release(actor) => {
+          if until.take().is_some() {
+            if let Err(err) = audit::scope(actor, async { self.door.write().await.release().await }).await {
+              log::error!("Releasing {name} failed: {err}");
+            }
+
+            log::info!("Released {name}.");
+            self.set_held_open(false).await;
+          }
+        },
+      }
+    }
+  }
+}
@@ -0,0 +1,105 @@
+use std::sync::{
+  Arc,
+  atomic::{AtomicBool, Ordering},
+};
+
+use smart_leds::RGB8;
+use tokio::sync::Mutex;
+
+use door_server::led::{RgbLed, RgbRing, Segment, closed_to_color};
+
+use crate::health::Health;
+
+const WARNING_COLOR: RGB8 = RGB8 { r: 0x14, g: 0x08, b: 0x00 };
+const ALARM_COLOR: RGB8 = RGB8 { r: 0x14, g: 0x00, b: 0x00 };
+const HELD_OPEN_COLOR: RGB8 = RGB8 { r: 0x00, g: 0x04, b: 0x14 };
+const OFF: RGB8 = RGB8 { r: 0x00, g: 0x00, b: 0x00 };
+
+#[derive(Default)]
+struct State {
+  closed: AtomicBool,
+  held_open: AtomicBool,
+}
+
+/// The ring segment and button LEDs showing the state of a door.
+#[derive(Clone)]
+pub struct Indicator {
+  ring: Option<Arc<Mutex<RgbRing>>>,
+  segment: Option<Segment>,
+  leds: Vec<Arc<Mutex<RgbLed>>>,
+  health: Health,
+  state: Arc<State>,
+}
+
+impl Indicator {
+  pub fn new(
+    ring: Option<Arc<Mutex<RgbRing>>>,
+    segment: Option<Segment>,
+    leds: Vec<Arc<Mutex<RgbLed>>>,
+    health: Health,
+  ) -> Self {
+    Self { ring, segment, leds, health, state: Default::default() }
+  }
+
+  async fn set_segment(&self, color: RGB8) {
+    if let (Some(ring), Some(segment)) = (&self.ring, self.segment) {
+      let mut ring = ring.lock().await;
+      ring.set_segment(segment, color);
+
+      match ring.render() {
+        Ok(()) => self.health.resolve("LED ring"),
+        Err(err) => self.health.report("LED ring", err),
+      }
+    }
+  }
+
+  async fn set_leds(&self, red: bool, green: bool, blue: bool) {
+    for led in &self.leds {
+      let mut led = led.lock().await;
+      let led = &mut *led;
+
+      for (pin, on) in [(&mut led.red, red), (&mut led.green, green), (&mut led.blue, blue)] {
+        if on { pin.set_high() } else { pin.set_low() }
+      }
+    }
+  }
+
+  pub async fn show_state(&self, closed: bool) {
+    self.state.closed.store(closed, Ordering::SeqCst);
+    self.refresh().await;
+  }
+
+  pub async fn show_held_open(&self, held_open: bool) {
+    self.state.held_open.store(held_open, Ordering::SeqCst);
+    self.refresh().await;
+  }
+
+  /// Show the last known state again, e.g. after a warning.
+  pub async fn refresh(&self) {
+    if self.state.held_open.load(Ordering::SeqCst) {
+      self.set_segment(HELD_OPEN_COLOR).await;
+      self.set_leds(false, false, true).await;
+    } else {
+      let closed = self.state.closed.load(Ordering::SeqCst);
+      self.set_segment(closed_to_color(closed)).await;
+      self.set_leds(!closed, closed, false).await;
+    }
+  }
+
+  /// Show one phase of a blinking warning.
+  pub async fn show_warning(&self, on: bool) {
+    self.set_segment(if on { WARNING_COLOR } else { OFF }).await;
+    self.set_leds(on, on, false).await;
+  }
+
+  /// Show one phase of the segment pulsing in its current color.
+  pub async fn show_pulse(&self, on: bool) {
+    if on { self.refresh().await } else { self.set_segment(OFF).await }
+  }
+
+  /// Show one phase of a blinking alarm.
+  pub async fn show_alarm(&self, on: bool) {
+    self.set_segment(if on { ALARM_COLOR } else { OFF }).await;
+    self.set_leds(on, false, false).await;
+  }
+}
@@ -0,0 +1,396 @@
+use std::{
+  collections::{BTreeSet, HashMap, HashSet},
+  error::Error,
+  fs, io,
+  path::Path,
+  sync::{
+    Arc, Mutex, RwLock,
+    atomic::{AtomicBool, Ordering},
+  },
+  time::{Duration, Instant},
+};
+
+use actix_rt::time::interval;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::sync::mpsc;
+use webthing::{BaseEvent, BaseProperty, BaseThing, Thing};
+
+use door_server::{Actuator, BoxFuture, Capability, Result, config::IntrusionConfig};
+
+use crate::{health::Health, indicator::Indicator, notify::notify, set_property};
+
+pub const THING_ID: &str = "urn:dev:ops:32473-house";
+
+const BLINK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Armed doors, as stored across restarts.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct State {
+  armed: BTreeSet<String>,
+}
+
+impl State {
+  fn load(path: &Path) -> Result<Option<Self>, Box<dyn Error>> {
+    match fs::read_to_string(path) {
+      Ok(state) => Ok(Some(serde_json::from_str(&state)?)),
+      Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+      Err(err) => Err(err.into()),
+    }
+  }
+
+  /// Replace the stored state atomically, so that it is never partially written.
+  fn save(&self, path: &Path) -> io::Result<()> {
+    let temporary_path = path.with_extension("json.tmp");
+    fs::write(&temporary_path, serde_json::to_string(self)?)?;
+    fs::rename(temporary_path, path)
+  }
+}
+
+#[derive(Debug)]
+enum Command {
+  Arm(Option<String>),
+  Disarm(Option<String>),
+  Intrusion(String),
+}
+
+/// Allows arming and disarming doors from actions.
+#[derive(Debug, Clone)]
+pub struct IntrusionHandle {
+  commands: mpsc::UnboundedSender<Command>,
+}
+
+impl IntrusionHandle {
+  /// Arm the given door, or all doors if `None`.
+  pub fn arm(&self, door: Option<String>) -> Result<(), Box<dyn Error>> {
+    self.commands.send(Command::Arm(door)).map_err(|_| "intrusion detection is not running".into())
+  }
+
+  /// Disarm the given door, or all doors if `None`.
+  pub fn disarm(&self, door: Option<String>) -> Result<(), Box<dyn Error>> {
+    self.commands.send(Command::Disarm(door)).map_err(|_| "intrusion detection is not running".into())
+  }
+}
+
+/// Checks whether openings of an armed door were preceded by an unlock or finger scan.
+#[derive(Debug, Clone)]
+pub struct Guard {
+  door: String,
+  window: Duration,
+  armed: Arc<AtomicBool>,
+  last_authorized: Arc<Mutex<Option<Instant>>>,
+  commands: mpsc::UnboundedSender<Command>,
+}
+
+impl Guard {
+  /// Expect the door to be opened within the window.
+  pub fn authorize(&self) {
+    *self.last_authorized.lock().unwrap() = Some(Instant::now());
+  }
+
+  /// Raise an intrusion if the door is armed and was neither authorised nor left unlocked.
+  pub fn opened(&self, left_unlocked: bool) {
+    let authorized = self.last_authorized.lock().unwrap().is_some_and(|time| time.elapsed() <= self.window);
+
+    if self.armed.load(Ordering::SeqCst) && !authorized && !left_unlocked {
+      let _ = self.commands.send(Command::Intrusion(self.door.clone()));
+    }
+  }
+
+  /// Wrap a door, so that opening it counts as authorised.
+  pub fn wrap(&self, door: Box<dyn Actuator>) -> Box<dyn Actuator> {
+    Box::new(Authorizing { door, guard: self.clone() })
+  }
+}
+
+#[derive(Debug)]
+struct Authorizing {
+  door: Box<dyn Actuator>,
+  guard: Guard,
+}
+
+impl Actuator for Authorizing {
+  fn capabilities(&self) -> &'static [Capability] {
+    self.door.capabilities()
+  }
+
+  fn open(&mut self, pulse: Option<Duration>) -> BoxFuture<'_, Result<()>> {
+    self.guard.authorize();
+    self.door.open(pulse)
+  }
+
+  fn close(&mut self, pulse: Option<Duration>) -> BoxFuture<'_, Result<()>> {
+    self.door.close(pulse)
+  }
+
+  fn closed(&self) -> Option<BoxFuture<'static, Result<()>>> {
+    self.door.closed()
+  }
+
+  fn stop(&mut self) -> BoxFuture<'_, Result<()>> {
+    self.door.stop()
+  }
+
+  fn toggle(&mut self) -> BoxFuture<'_, Result<()>> {
+    self.guard.authorize();
+    self.door.toggle()
+  }
+
+  fn hold(&mut self) -> BoxFuture<'_, Result<()>> {
+    self.guard.authorize();
+    self.door.hold()
+  }
+
+  fn release(&mut self) -> BoxFuture<'_, Result<()>> {
+    self.door.release()
+  }
+}
+
+struct GuardedDoor {
+  thing: Arc<RwLock<Box<dyn Thing>>>,
+  indicator: Indicator,
+}
+
+/// House-level thing for arming doors, raising an alarm when an armed door is opened unexpectedly.
+pub struct Intrusion {
+  config: IntrusionConfig,
+  thing: Arc<RwLock<Box<dyn Thing>>>,
+  guards: HashMap<String, Guard>,
+  doors: HashMap<String, GuardedDoor>,
+  alarms: HashSet<String>,
+  commands: mpsc::UnboundedReceiver<Command>,
+  handle: IntrusionHandle,
+  health: Health,
+}
+
+impl Intrusion {
+  pub fn new(config: IntrusionConfig, health: Health) -> Self {
+    let (sender, commands) = mpsc::unbounded_channel();
+
+    let guards = config
+      .doors
+      .iter()
+      .map(|door| {
+        let guard = Guard {
+          door: door.clone(),
+          window: config.window,
+          armed: Default::default(),
+          last_authorized: Default::default(),
+          commands: sender.clone(),
+        };
+        (door.clone(), guard)
+      })
+      .collect();
+
+    let mut thing = BaseThing::new(
+      THING_ID.to_owned(),
+      "House".to_owned(),
+      Some(vec!["Alarm".to_owned()]),
+      Some("Intrusion Detection".to_owned()),
+    );
+
+    let alarm = json!({
+      "@type": "AlarmProperty",
+      "title": "Alarm",
+      "type": "boolean",
+      "description": "Whether an armed door has been opened without being unlocked.",
+      "readOnly": true,
+    });
+    thing.add_property(Box::new(BaseProperty::new(
+      "alarm".into(),
+      json!(false),
+      None,
+      Some(alarm.as_object().unwrap().to_owned()),
+    )));
+
+    let input = json!({
+      "type": ["object", "null"],
+      "properties": {
+        "door": {
+          "title": "Door",
+          "description": "ID of the door, or all doors if omitted.",
+          "type": "string",
+          "enum": config.doors,
+        },
+      },
+    });
+    let actions = [
+      ("arm", json!({ "title": "Arm", "description": "Arm the door.", "input": input })),
+      ("disarm", json!({ "title": "Disarm", "description": "Disarm the door and stop its alarm.", "input": input })),
+    ];
+    for (action_name, metadata) in actions {
+      thing.add_available_action(action_name.into(), metadata.as_object().unwrap().to_owned());
+    }
+
+    thing.add_available_event(
+      "restored".to_owned(),
+      json!({
+        "description": "The doors armed at startup differ from the doors armed before, e.g. because the state was lost.",
+      })
+      .as_object()
+      .unwrap()
+      .to_owned(),
+    );
+
+    Self {
+      config,
+      thing: Arc::new(RwLock::new(Box::new(thing))),
+      guards,
+      doors: HashMap::new(),
+      alarms: HashSet::new(),
+      commands,
+      handle: IntrusionHandle { commands: sender },
+      health,
+    }
+  }
+
+  pub fn handle(&self) -> IntrusionHandle {
+    self.handle.clone()
+  }
+
+  pub fn thing(&self) -> Arc<RwLock<Box<dyn Thing>>> {
+    self.thing.clone()
+  }
+
+  /// Get the guard of a door, if it can be armed.
+  pub fn guard(&self, door: &str) -> Option<Guard> {
+    self.guards.get(door).cloned()
+  }
+
+  /// Add the `armed` property and the `intrusion` event to a door which can be armed.
+  pub fn add_door(&mut self, door: &str, thing: Arc<RwLock<Box<dyn Thing>>>, indicator: Indicator) {
+    {
+      let mut thing = thing.write().unwrap();
+
+      let armed = json!({
+        "title": "Armed",
+        "type": "boolean",
+        "description": "Whether opening the door without unlocking it raises an intrusion.",
+        "readOnly": true,
+      });
+      thing.add_property(Box::new(BaseProperty::new(
+        "armed".into(),
+        json!(false),
+        None,
+        Some(armed.as_object().unwrap().to_owned()),
+      )));
+
+      thing.add_available_event(
+        "intrusion".to_owned(),
+        json!({
+          "description": "The door has been opened while armed without being unlocked.",
+        })
+        .as_object()
+        .unwrap()
+        .to_owned(),
+      );
+    }
+
+    self.doors.insert(door.to_owned(), GuardedDoor { thing, indicator });
+  }
+
+  async fn set_armed(&mut self, door: Option<String>, armed: bool) {
+    let doors = match door {
+      Some(door) => vec![door],
+      None => self.guards.keys().cloned().collect(),
+    };
+
+    for door in doors {
+      let Some(guard) = self.guards.get(&door) else { continue };
+      guard.armed.store(armed, Ordering::SeqCst);
+
+      let Some(guarded) = self.doors.get(&door) else { continue };
+      let name = guarded.thing.read().unwrap().get_title();
+      log::info!("{name} {}.", if armed { "armed" } else { "disarmed" });
+      set_property(guarded.thing.write().unwrap(), "armed", json!(armed));
+
+      if !armed && self.alarms.remove(&door) {
+        guarded.indicator.refresh().await;
+      }
+    }
+
+    set_property(self.thing.write().unwrap(), "alarm", json!(!self.alarms.is_empty()));
+    self.save();
+  }
+
+  fn armed(&self) -> BTreeSet<String> {
+    self.guards.iter().filter(|(_, guard)| guard.armed.load(Ordering::SeqCst)).map(|(door, _)| door.clone()).collect()
+  }
+
+  fn save(&self) {
+    match (State { armed: self.armed() }).save(&self.config.path) {
+      Ok(()) => self.health.resolve("Intrusion state"),
+      Err(err) => self.health.report("Intrusion state", err),
+    }
+  }
+
+  /// Arm the doors which were armed before the last restart.
+  async fn restore(&mut self) {
+    let previous = match State::load(&self.config.path) {
+      Ok(Some(state)) => Some(state.armed),
+      Ok(None) => return,
+      Err(err) => {
+        log::error!("Restoring armed doors from {} failed: {err}", self.config.path.display());
+        None
+      },
+    };
+
+    // Doors which are no longer configured cannot be armed anymore.
+    let armed =
+      previous.iter().flatten().filter(|door| self.guards.contains_key(*door)).cloned().collect::<BTreeSet<_>>();
+    for door in &armed {
+      self.set_armed(Some(door.clone()), true).await;
+    }
+
+    if previous.as_ref() != Some(&armed) {
+      if let Some(previous) = &previous {
+        let join = |doors: &BTreeSet<String>| doors.iter().cloned().collect::<Vec<_>>().join(", ");
+        log::warn!("Restored armed doors [{}], but [{}] were armed before.", join(&armed), join(previous));
+      }
+
+      let event = BaseEvent::new("restored".to_owned(), Some(json!({ "armed": armed, "previous": previous })));
+      self.thing.write().unwrap().add_event(Box::new(event));
+      self.save();
+    }
+  }
+
+  fn raise(&mut self, door: String) {
+    let Some(GuardedDoor { thing, .. }) = self.doors.get(&door) else { return };
+
+    let name = thing.read().unwrap().get_title();
+    log::warn!("{name} has been opened without being unlocked.");
+
+    thing.write().unwrap().add_event(Box::new(BaseEvent::new("intrusion".to_owned(), None)));
+    notify(&self.config.notify, "intrusion", &door, &name);
+
+    self.alarms.insert(door);
+    set_property(self.thing.write().unwrap(), "alarm", json!(true));
+  }
+
+  pub async fn run(mut self) {
+    self.restore().await;
+
+    let mut blink = interval(BLINK_INTERVAL);
+    let mut on = false;
+
+    loop {
+      tokio::select! {
+        command = self.commands.recv() => match command {
+          Some(Command::Arm(door)) => self.set_armed(door, true).await,
+          Some(Command::Disarm(door)) => self.set_armed(door, false).await,
+          Some(Command::Intrusion(door)) => self.raise(door),
+          None => return,
+        },
+        _ = blink.tick(), if !self.alarms.is_empty() => {
+          on = !on;
+
+          for door in &self.alarms {
+            if let Some(guarded) = self.doors.get(door) {
+              guarded.indicator.show_alarm(on).await;
+            }
+          }
+        },
+      }
+    }
+  }
+}
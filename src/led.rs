@@ -1,22 +1,32 @@
-use rppal::spi::{Bus, Mode as SpiMode, SlaveSelect, Spi};
-use smart_leds::{RGB8, SmartLedsWrite};
-use ws2812_spi::hosted::Ws2812;
+use serde::Deserialize;
+use smart_leds::RGB8;
+
+use crate::{
+  Result,
+  hal::{LedStrip, OutputPin},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Segment {
+  TopRight,
+  BottomRight,
+  BottomLeft,
+  TopLeft,
+}
+
+impl Segment {
+  pub const ALL: [Self; 4] = [Self::TopRight, Self::BottomRight, Self::BottomLeft, Self::TopLeft];
+}
 
 pub struct RgbRing {
-  inner: Ws2812<Spi>,
+  inner: Box<dyn LedStrip>,
   colors: [RGB8; 12],
 }
 
 impl RgbRing {
-  #[allow(clippy::new_without_default)]
-  pub fn new() -> Self {
-    // On Raspberry Pi, `core_freq=250` must be set in `/boot/config.txt` in order to have a stable SPI frequency.
-    let spi_freq = 800_000 * 3;
-
-    let spi = Spi::new(Bus::Spi0, SlaveSelect::Ss0, spi_freq, SpiMode::Mode0).unwrap();
-    let ws2812 = Ws2812::new(spi);
-
-    Self { inner: ws2812, colors: Default::default() }
+  pub fn new(led_strip: Box<dyn LedStrip>) -> Self {
+    Self { inner: led_strip, colors: Default::default() }
   }
 
   pub fn set_top_right(&mut self, color: RGB8) {
@@ -35,11 +45,27 @@ impl RgbRing {
     self.colors[9..=11].fill(color);
   }
 
-  pub fn render(&mut self) {
-    self.inner.write(self.colors.iter().cloned()).unwrap();
+  pub fn set_segment(&mut self, segment: Segment, color: RGB8) {
+    match segment {
+      Segment::TopRight => self.set_top_right(color),
+      Segment::BottomRight => self.set_bottom_right(color),
+      Segment::BottomLeft => self.set_bottom_left(color),
+      Segment::TopLeft => self.set_top_left(color),
+    }
+  }
+
+  pub fn render(&mut self) -> Result<()> {
+    self.inner.write(&self.colors)
   }
 }
 
 pub fn closed_to_color(closed: bool) -> RGB8 {
   if closed { RGB8 { r: 0x00, g: 0x0f, b: 0x01 } } else { RGB8 { r: 0x14, g: 0x00, b: 0x00 } }
 }
+
+#[derive(Debug)]
+pub struct RgbLed {
+  pub red: Box<dyn OutputPin>,
+  pub green: Box<dyn OutputPin>,
+  pub blue: Box<dyn OutputPin>,
+}
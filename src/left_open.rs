@@ -0,0 +1,124 @@
+use std::{
+  sync::{Arc, RwLock},
+  time::Duration,
+};
+
+use actix_rt::time::{Instant, sleep, sleep_until};
+use serde_json::json;
+use tokio::sync::watch;
+use webthing::{BaseEvent, BaseProperty, Thing};
+
+use door_server::config::LeftOpenConfig;
+
+use crate::{indicator::Indicator, notify::notify, set_property};
+
+const PULSE_INTERVAL: Duration = Duration::from_secs(1);
+
+enum Tick {
+  Alarm,
+  Pulse,
+  Closed,
+}
+
+/// Raises repeated alarms while a door has been left open for too long, pulsing its ring segment.
+pub struct LeftOpen {
+  config: LeftOpenConfig,
+  door: String,
+  thing: Arc<RwLock<Box<dyn Thing>>>,
+  indicator: Indicator,
+  closed: watch::Receiver<bool>,
+}
+
+impl LeftOpen {
+  pub fn new(
+    config: LeftOpenConfig,
+    door: String,
+    thing: Arc<RwLock<Box<dyn Thing>>>,
+    indicator: Indicator,
+    closed: watch::Receiver<bool>,
+  ) -> Self {
+    {
+      let mut thing = thing.write().unwrap();
+
+      let door_open_too_long = json!({
+        "title": "Open Too Long",
+        "type": "boolean",
+        "description": "Whether the door has been left open for too long.",
+        "readOnly": true,
+      });
+      thing.add_property(Box::new(BaseProperty::new(
+        "door_open_too_long".into(),
+        json!(false),
+        None,
+        Some(door_open_too_long.as_object().unwrap().to_owned()),
+      )));
+
+      thing.add_available_event(
+        "left_open".to_owned(),
+        json!({
+          "description": "The door has been left open for the given time.",
+          "type": "integer",
+          "unit": "second",
+        })
+        .as_object()
+        .unwrap()
+        .to_owned(),
+      );
+    }
+
+    Self { config, door, thing, indicator, closed }
+  }
+
+  fn alarm(&self, name: &str, open_for: Duration) {
+    log::warn!("{name} has been left open for {:?}.", Duration::from_secs(open_for.as_secs()));
+
+    let event = Box::new(BaseEvent::new("left_open".to_owned(), Some(json!(open_for.as_secs()))));
+    let mut thing = self.thing.write().unwrap();
+    thing.add_event(event);
+    set_property(thing, "door_open_too_long", json!(true));
+
+    notify(&self.config.notify, "left_open", &self.door, name);
+  }
+
+  pub async fn run(mut self) {
+    let name = self.thing.read().unwrap().get_title();
+
+    loop {
+      if self.closed.wait_for(|closed| !*closed).await.is_err() {
+        return
+      }
+
+      let opened = Instant::now();
+      let mut deadline = Some(opened + self.config.after);
+      let mut too_long = false;
+      let mut on = false;
+
+      loop {
+        let tick = tokio::select! {
+          _ = sleep_until(deadline.unwrap_or(opened)), if deadline.is_some() => Tick::Alarm,
+          _ = sleep(PULSE_INTERVAL), if too_long => Tick::Pulse,
+          _ = self.closed.wait_for(|closed| *closed) => Tick::Closed,
+        };
+
+        match tick {
+          Tick::Alarm => {
+            too_long = true;
+            self.alarm(&name, opened.elapsed());
+            deadline = self.config.repeat.map(|repeat| Instant::now() + repeat);
+          },
+          Tick::Pulse => {
+            on = !on;
+            self.indicator.show_pulse(on).await;
+          },
+          Tick::Closed => break,
+        }
+      }
+
+      if too_long {
+        log::info!("{name} has been closed.");
+        set_property(self.thing.write().unwrap(), "door_open_too_long", json!(false));
+        self.indicator.refresh().await;
+      }
+    }
+  }
+}
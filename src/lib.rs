@@ -1,37 +1,55 @@
-use std::{future::Future, sync::Arc, thread};
+use std::future::Future;
 
-use rppal::gpio::{Event, Trigger};
-use tokio::{runtime::Runtime, sync::Mutex};
+use tokio::sync::watch;
 
-mod board;
-pub use board::Board;
+mod actuator;
+pub use actuator::{Actuator, BoxFuture, Capability, close_and_wait};
+
+pub mod config;
+
+mod error;
+pub use error::{Error, Result};
 
 mod door;
 pub use door::Door;
 
+mod event_bus;
+pub use event_bus::EventBus;
+
 mod garage_door;
-pub use garage_door::GarageDoor;
+pub use garage_door::{GarageDoor, Motion};
+
+pub mod hal;
 
 pub mod led;
 
+mod motor_lock;
+pub use motor_lock::{LockState, MotorLock};
+
 pub trait StatefulDoor {
-  fn on_change<C, F>(&mut self, callback: C)
+  /// Call `callback` via `bus` whenever the door is opened or closed.
+  fn on_change<C, F>(&mut self, bus: &EventBus, callback: C) -> Result<()>
   where
-    F: Future,
-    C: (FnMut(bool) -> F) + Send + 'static;
+    F: Future + 'static,
+    C: (FnMut(bool) -> F) + 'static;
 
   fn is_closed(&self) -> bool;
 
   fn is_open(&self) -> bool;
+
+  /// Watch the state of a lock which is separate from the door contact, if there is one.
+  fn lock_state(&self) -> Option<watch::Receiver<LockState>> {
+    None
+  }
 }
 
 impl<T: StatefulDoor> StatefulDoor for &mut T {
-  fn on_change<C, F>(&mut self, callback: C)
+  fn on_change<C, F>(&mut self, bus: &EventBus, callback: C) -> Result<()>
   where
-    F: Future,
-    C: (FnMut(bool) -> F) + Send + 'static,
+    F: Future + 'static,
+    C: (FnMut(bool) -> F) + 'static,
   {
-    (**self).on_change(callback)
+    (**self).on_change(bus, callback)
   }
 
   fn is_closed(&self) -> bool {
@@ -41,24 +59,8 @@ impl<T: StatefulDoor> StatefulDoor for &mut T {
   fn is_open(&self) -> bool {
     (**self).is_open()
   }
-}
 
-pub fn on_change_async<C, F>(callback: C) -> impl FnMut(Event) + Send + 'static
-where
-  F: Future,
-  C: (FnMut(bool) -> F) + Send + 'static,
-{
-  let callback = Arc::new(Mutex::new(callback));
-
-  move |event: Event| {
-    let callback = callback.clone();
-
-    thread::spawn(move || {
-      let rt: Runtime = Runtime::new().unwrap();
-      rt.block_on(async move {
-        let closed = event.trigger == Trigger::FallingEdge;
-        callback.lock().await(closed).await;
-      })
-    });
+  fn lock_state(&self) -> Option<watch::Receiver<LockState>> {
+    (**self).lock_state()
   }
 }
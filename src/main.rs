@@ -1,31 +1,99 @@
 use std::{
-  any::Any,
   collections::HashMap,
   env,
-  future::Future,
+  future::{self, Future},
   io,
   ops::DerefMut,
-  str,
+  path::Path,
+  process, str,
   sync::{Arc, RwLock, Weak},
   time::Duration,
 };
 
-use rppal::gpio::{Gpio, Trigger};
+use actix_web::{App, HttpServer, middleware, web};
 use serde_json::json;
-use tokio::{net::UdpSocket, signal, sync::Mutex};
+use smart_leds::RGB8;
+use tokio::{
+  net::UdpSocket,
+  signal::{self, unix::SignalKind},
+  sync::{Mutex, broadcast, watch},
+};
 use webthing::{
   Action, BaseEvent, BaseProperty, BaseThing, Thing, ThingsType, WebThingServer, server::ActionGenerator,
 };
 
-use door_server::{Door, GarageDoor, StatefulDoor, on_change_async};
+use door_server::{Actuator, Capability, Door, EventBus, GarageDoor, Motion, MotorLock, StatefulDoor};
 
 mod action;
-use action::{LockAction, UnlockAction};
+use action::{
+  ArmAction, DisarmAction, HoldOpenAction, LockAction, ReleaseAction, SharedDoor, StopAction, ToggleAction,
+  UnlockAction, is_supported,
+};
+
+mod attribution;
+use attribution::Attribution;
+
+mod audit;
+use audit::Audit;
+
+mod auth;
+use auth::Auth;
+
+mod auto_close;
+use auto_close::AutoClose;
+
+mod discovery;
+
+mod guests;
+use guests::Guests;
+
+mod health;
+use health::Health;
+
+mod history;
+use history::History;
+
+mod hold_open;
+use hold_open::{HoldOpen, HoldOpenHandle};
+
+mod indicator;
+use indicator::Indicator;
+
+mod intrusion;
+use intrusion::{Guard, Intrusion, IntrusionHandle};
+
+mod left_open;
+use left_open::LeftOpen;
+
+mod mqtt;
+use mqtt::Mqtt;
 
-use door_server::{Board, led::closed_to_color};
+mod notify;
 
+mod simulation;
+use simulation::Simulation;
+
+mod subscription;
+use subscription::Kind;
+
+mod tls;
+
+#[cfg(feature = "rppal")]
+use door_server::hal::RppalBackend;
+use door_server::{
+  config::{ButtonType, Config, DoorConfig, DoorType, LedPins},
+  hal::{Backend, MemoryBackend},
+  led::{RgbLed, RgbRing, Segment},
+};
+
+const CONFIG_PATH: &str = "/etc/door-server.toml";
+const DEFAULT_CONFIG: &str = include_str!("../door-server.toml");
+
+#[derive(Clone)]
 struct Generator {
-  doors: HashMap<String, Arc<tokio::sync::RwLock<Box<dyn Any + Send + Sync>>>>,
+  doors: HashMap<String, (&'static [Capability], SharedDoor)>,
+  hold_opens: HashMap<String, HoldOpenHandle>,
+  intrusion: Option<IntrusionHandle>,
 }
 
 impl ActionGenerator for Generator {
@@ -33,18 +101,39 @@ impl ActionGenerator for Generator {
     &self,
     thing: Weak<RwLock<Box<dyn Thing>>>,
     name: String,
-    _input: Option<&serde_json::Value>,
+    input: Option<&serde_json::Value>,
   ) -> Option<Box<dyn Action>> {
     let id = {
       let thing = thing.upgrade()?;
       let thing = thing.read().unwrap();
       thing.get_id()
     };
-    let door = self.doors.get(&id)?.clone();
+    let input = input.and_then(|input| input.as_object()).cloned();
+
+    if id == intrusion::THING_ID {
+      let intrusion = self.intrusion.clone()?;
+
+      return match name.as_str() {
+        "arm" => Some(Box::new(ArmAction::new(thing, intrusion, input))),
+        "disarm" => Some(Box::new(DisarmAction::new(thing, intrusion, input))),
+        _ => None,
+      }
+    }
+
+    let (capabilities, door) = self.doors.get(&id)?.clone();
+
+    if !is_supported(&name, capabilities) {
+      log::warn!("Rejecting unsupported action {name}.");
+      return None
+    }
 
     match name.as_str() {
-      "lock" => Some(Box::new(LockAction::new(thing, door))),
-      "unlock" => Some(Box::new(UnlockAction::new(thing, door))),
+      "lock" => Some(Box::new(LockAction::new(thing, door, input))),
+      "unlock" => Some(Box::new(UnlockAction::new(thing, door, input))),
+      "stop" => Some(Box::new(StopAction::new(thing, door, input))),
+      "toggle" => Some(Box::new(ToggleAction::new(thing, door, input))),
+      "hold_open" => Some(Box::new(HoldOpenAction::new(thing, self.hold_opens.get(&id)?.clone(), input))),
+      "release" => Some(Box::new(ReleaseAction::new(thing, self.hold_opens.get(&id)?.clone(), input))),
       _ => None,
     }
   }
@@ -72,45 +161,114 @@ fn set_property<T: DerefMut<Target = Box<dyn Thing + 'static>>>(
   }
 }
 
-async fn make_door_thing<OC, F>(
-  mut door: impl StatefulDoor,
-  id: &str,
-  name: &str,
-  supports_locking: bool,
+/// Input of the `unlock` and `lock` actions, limited by the door configuration.
+fn trigger_input(config: &DoorConfig) -> serde_json::Value {
+  let mut properties = serde_json::Map::new();
+
+  if config.ty != DoorType::MotorLock {
+    properties.insert(
+      "pulse".into(),
+      json!({
+        "title": "Pulse",
+        "description": "Length of the trigger pulse.",
+        "type": "integer",
+        "minimum": 1,
+        "maximum": config.max_pulse().as_millis(),
+        "unit": "millisecond",
+      }),
+    );
+  }
+
+  properties.insert(
+    "delay".into(),
+    json!({
+      "title": "Delay",
+      "description": "Time to wait before triggering the door.",
+      "type": "integer",
+      "minimum": 0,
+      "maximum": config.max_delay().as_secs(),
+      "unit": "second",
+    }),
+  );
+
+  // Also allow `null`, so the action can still be requested without any input.
+  json!({
+    "type": ["object", "null"],
+    "properties": properties,
+  })
+}
+
+async fn make_door_thing<D, OC, F>(
+  door: &mut D,
+  config: &DoorConfig,
+  bus: &EventBus,
+  health: &Health,
+  attribution: Option<Attribution>,
+  guard: Option<Guard>,
   mut on_change: OC,
 ) -> Arc<RwLock<Box<dyn Thing + 'static>>>
 where
-  F: Future + Send + Sync + 'static,
-  OC: (FnMut(bool) -> F) + Send + Sync + 'static,
+  D: StatefulDoor + Actuator,
+  F: Future + 'static,
+  OC: (FnMut(bool) -> F) + 'static,
 {
+  let name = &config.name;
   let mut door_thing = BaseThing::new(
-    format!("urn:dev:ops:32473-{}", id),
+    format!("urn:dev:ops:32473-{}", config.id),
     name.to_owned(),
-    Some(vec!["Lock".to_owned()]),
+    Some(vec!["Lock".to_owned(), "DoorSensor".to_owned()]),
     Some("Door Opener and Contact Sensor".to_owned()),
   );
 
   let door_locked = json!({
     "@type": "LockedProperty",
     "title": "Door Lock Status",
+    "type": "string",
+    "enum": ["locked", "unlocked", "jammed", "unknown"],
+    "description": "Whether or not the door is currently locked.",
+    "readOnly": true,
+  });
+
+  let door_open = json!({
+    "@type": "OpenProperty",
+    "title": "Open",
     "type": "boolean",
     "description": "Whether or not the door is currently open.",
     "readOnly": true,
   });
 
+  let lock_state = door.lock_state();
+  let lock = match &lock_state {
+    Some(lock_state) => json!(*lock_state.borrow()),
+    None => door_state(Some(door.is_closed())),
+  };
+
   door_thing.add_property(Box::new(BaseProperty::new(
     "lock".into(),
-    door_state(Some(door.is_closed())),
+    lock,
     None,
     Some(door_locked.as_object().unwrap().to_owned()),
   )));
 
-  let door_unlock = json!({
-    "title": "Unlock",
-    "description": "Unlock the door.",
-  });
+  door_thing.add_property(Box::new(BaseProperty::new(
+    "open".into(),
+    json!(door.is_open()),
+    None,
+    Some(door_open.as_object().unwrap().to_owned()),
+  )));
+
+  let actions = [
+    ("unlock", json!({ "title": "Unlock", "description": "Unlock the door.", "input": trigger_input(config) })),
+    ("lock", json!({ "title": "Lock", "description": "Lock the door.", "input": trigger_input(config) })),
+    ("stop", json!({ "title": "Stop", "description": "Stop the door." })),
+    ("toggle", json!({ "title": "Toggle", "description": "Close the door if it is open, otherwise open it." })),
+  ];
 
-  door_thing.add_available_action("unlock".into(), door_unlock.as_object().unwrap().to_owned());
+  for (action_name, metadata) in actions {
+    if is_supported(action_name, door.capabilities()) {
+      door_thing.add_available_action(action_name.into(), metadata.as_object().unwrap().to_owned());
+    }
+  }
 
   door_thing.add_available_event(
     "finger_scan".to_owned(),
@@ -124,13 +282,21 @@ where
     .to_owned(),
   );
 
-  if supports_locking {
-    let door_lock = json!({
-      "title": "Lock",
-      "description": "Lock the door.",
-    });
+  if let Some(attribution) = &attribution {
+    attribution.add_to(&mut door_thing);
+  }
 
-    door_thing.add_available_action("lock".into(), door_lock.as_object().unwrap().to_owned());
+  if door.supports(Capability::Close) {
+    door_thing.add_available_event(
+      "close_failed".to_owned(),
+      json!({
+        "description": "The door could not be closed or locked.",
+        "type": "string",
+      })
+      .as_object()
+      .unwrap()
+      .to_owned(),
+    );
   }
 
   let thing: Arc<RwLock<Box<dyn Thing + 'static>>> = Arc::new(RwLock::new(Box::new(door_thing)));
@@ -139,194 +305,598 @@ where
   on_change(door.is_closed()).await;
 
   let thing_clone = thing.clone();
-  door.on_change(move |closed| {
+  let lock_follows_contact = lock_state.is_none();
+  let result = door.on_change(bus, move |closed| {
     let thing = thing_clone.clone();
+    let attribution = attribution.clone();
+    let guard = guard.clone();
     let on_change = on_change(closed);
 
     async move {
-      let thing = thing.write().unwrap();
-      let value = door_state(Some(closed));
-      set_property(thing, "lock", value);
+      {
+        let mut thing = thing.write().unwrap();
+
+        let opened = !closed && thing.get_property("open") == Some(json!(false));
+        let left_unlocked = thing.get_property("held_open") == Some(json!(true))
+          || !lock_follows_contact && thing.get_property("lock") == Some(json!("unlocked"));
+
+        set_property(&mut *thing, "open", json!(!closed));
+        if opened && let Some(attribution) = &attribution {
+          attribution.opened(&mut thing);
+        }
+        if opened && let Some(guard) = &guard {
+          guard.opened(left_unlocked);
+        }
+
+        if lock_follows_contact {
+          set_property(&mut *thing, "lock", door_state(Some(closed)));
+        }
+      }
 
       on_change.await
     }
   });
 
+  if let Err(err) = result {
+    health.report(&format!("{name} contact"), err);
+  }
+
+  if let Some(mut lock_state) = lock_state {
+    let thing = thing.clone();
+    actix_rt::spawn(async move {
+      while lock_state.changed().await.is_ok() {
+        let value = json!(*lock_state.borrow_and_update());
+        set_property(thing.write().unwrap(), "lock", value);
+      }
+    });
+  }
+
   thing
 }
 
+fn add_motion_property(thing: &Arc<RwLock<Box<dyn Thing + 'static>>>, mut motion: watch::Receiver<Motion>) {
+  let door_motion = json!({
+    "title": "Motion",
+    "type": "string",
+    "enum": ["opening", "closing", "open", "closed", "stopped", "unknown"],
+    "description": "Whether the door is currently moving or in which position it stopped.",
+    "readOnly": true,
+  });
+
+  let value = json!(*motion.borrow_and_update());
+  thing.write().unwrap().add_property(Box::new(BaseProperty::new(
+    "motion".into(),
+    value,
+    None,
+    Some(door_motion.as_object().unwrap().to_owned()),
+  )));
+
+  let thing = thing.clone();
+  actix_rt::spawn(async move {
+    while motion.changed().await.is_ok() {
+      let value = json!(*motion.borrow_and_update());
+      set_property(thing.write().unwrap(), "motion", value);
+    }
+  });
+}
+
+fn add_open_failed_event(thing: &Arc<RwLock<Box<dyn Thing + 'static>>>, mut open_failed: broadcast::Receiver<()>) {
+  thing.write().unwrap().add_available_event(
+    "open_failed".to_owned(),
+    json!({
+      "description": "The door was still closed when it should have been open.",
+      "type": "string",
+    })
+    .as_object()
+    .unwrap()
+    .to_owned(),
+  );
+
+  let thing = thing.clone();
+  actix_rt::spawn(async move {
+    while let Ok(()) | Err(broadcast::error::RecvError::Lagged(_)) = open_failed.recv().await {
+      log::warn!("{} did not open.", thing.read().unwrap().get_title());
+      let event = BaseEvent::new("open_failed".to_owned(), Some(json!(door_server::Error::OpenFailed.to_string())));
+      thing.write().unwrap().add_event(Box::new(event));
+    }
+  });
+}
+
+fn make_door(backend: &mut dyn Backend, config: &DoorConfig) -> door_server::Result<Door> {
+  let door = Door::new(backend.trigger_pin(config.pins.open)?, backend.input_pin(config.pins.contact)?);
+  Ok(door.with_pulse(config.pulse()))
+}
+
+fn make_garage_door(backend: &mut dyn Backend, config: &DoorConfig) -> door_server::Result<GarageDoor> {
+  let pins = &config.pins;
+
+  Ok(
+    GarageDoor::new(
+      backend.trigger_pin(pins.open)?,
+      backend.trigger_pin(pins.stop.unwrap())?,
+      backend.trigger_pin(pins.close.unwrap())?,
+      backend.input_pin(pins.contact)?,
+      config.travel_time(),
+      config.close_timeout(),
+    )
+    .with_pulse(config.pulse())
+    .with_stop_settle(config.stop_settle()),
+  )
+}
+
+fn make_motor_lock(backend: &mut dyn Backend, config: &DoorConfig) -> door_server::Result<MotorLock> {
+  let pins = &config.pins;
+
+  Ok(MotorLock::new(
+    backend.trigger_pin(pins.close.unwrap())?,
+    backend.trigger_pin(pins.open)?,
+    backend.input_pin(pins.contact)?,
+    backend.input_pin(pins.bolt.unwrap())?,
+    config.travel_time(),
+  ))
+}
+
+fn make_rgb_led(backend: &mut dyn Backend, pins: &LedPins) -> door_server::Result<RgbLed> {
+  Ok(RgbLed {
+    red: backend.output_pin(pins.red)?,
+    green: backend.output_pin(pins.green)?,
+    blue: backend.output_pin(pins.blue)?,
+  })
+}
+
 #[actix_rt::main]
 async fn main() {
   env_logger::init();
 
   let port = env::var("PORT").map(|s| s.parse::<u16>().expect("Port is invalid")).unwrap_or(8888);
 
-  let gpio = Gpio::new().unwrap();
-  let board = Board::new(gpio);
+  let mut simulate = false;
+  let mut init_audit = false;
+  let mut verify_audit = false;
+  let mut config_path = None;
+
+  let mut args = env::args().skip(1);
+  while let Some(arg) = args.next() {
+    match arg.as_str() {
+      "init-audit" => init_audit = true,
+      "verify-audit" => verify_audit = true,
+      "--simulate" => simulate = true,
+      "--config" => config_path = Some(args.next().expect("Configuration path is missing")),
+      arg => {
+        log::error!("Unknown argument: {arg}");
+        process::exit(1)
+      },
+    }
+  }
+
+  let config = match config_path {
+    Some(path) => Config::load(path),
+    None if Path::new(CONFIG_PATH).exists() => Config::load(CONFIG_PATH),
+    None => {
+      log::info!("No configuration found at {CONFIG_PATH}, using default configuration.");
+      DEFAULT_CONFIG.parse()
+    },
+  };
+  let config = match config {
+    Ok(config) => config,
+    Err(err) => {
+      log::error!("{err}");
+      process::exit(1)
+    },
+  };
+
+  if init_audit || verify_audit {
+    let Some(audit) = &config.audit else {
+      log::error!("No `audit` section configured.");
+      process::exit(1)
+    };
+
+    if init_audit {
+      match Audit::init(audit) {
+        Ok(()) => {
+          println!("Initialised {}.", audit.path.display());
+          process::exit(0)
+        },
+        Err(err) => {
+          println!("Initialising {} failed: {err}", audit.path.display());
+          process::exit(1)
+        },
+      }
+    }
+
+    match audit::read_key(&audit.key_file).map_err(Into::into).and_then(|key| audit::verify(&audit.path, &key)) {
+      Ok(entries) => {
+        println!("Verified {entries} entries of {}.", audit.path.display());
+        process::exit(0)
+      },
+      Err(err) => {
+        println!("Verifying {} failed: {err}", audit.path.display());
+        process::exit(1)
+      },
+    }
+  }
+
+  let simulation = if simulate { Some(Simulation::new(MemoryBackend::new(), &config)) } else { None };
+
+  let mut backend: Box<dyn Backend> = if let Some(simulation) = &simulation {
+    log::info!("Simulating doors.");
+    Box::new(simulation.backend())
+  } else {
+    #[cfg(feature = "rppal")]
+    match RppalBackend::new() {
+      Ok(backend) => Box::new(backend),
+      Err(err) => {
+        log::error!("{err}");
+        process::exit(1)
+      },
+    }
+    #[cfg(not(feature = "rppal"))]
+    {
+      log::error!("Built without Raspberry Pi support, only `--simulate` is available.");
+      process::exit(1)
+    }
+  };
 
   let mut things = Vec::new();
+  // Door ID of every thing, by index.
+  let mut thing_ids = Vec::new();
   let mut doors = HashMap::new();
+  let mut door_things = HashMap::new();
+  let mut auto_closes = HashMap::new();
+  let mut hold_opens = HashMap::new();
+  let mut attributions = HashMap::new();
+  let health = Health::default();
+  let mut intrusion = config.intrusion.clone().map(|intrusion| Intrusion::new(intrusion, health.clone()));
+  let bus = EventBus::new();
 
-  let led = Arc::new(Mutex::new(board.led));
-  let ring = Arc::new(Mutex::new(board.ring));
+  let audit = match config.audit.as_ref().map(|audit| Audit::open(audit, health.clone())) {
+    Some(Ok(audit)) => Some(audit),
+    Some(Err(err)) => {
+      log::error!("Opening audit log failed: {err}");
+      process::exit(1)
+    },
+    None => None,
+  };
 
-  let mut door_bell_button = board.main_door_bell;
-  let mut garage_door_button = board.garage_door_button;
+  let history = match config.history.enabled.then(|| History::open(&config.history.path, health.clone())) {
+    Some(Ok(history)) => Some(history),
+    Some(Err(err)) => {
+      health.report("History", err);
+      None
+    },
+    None => None,
+  };
 
-  let mut main_door = Door::new(board.main_door_open, board.main_door_contact);
-  let ring_clone = ring.clone();
-  let main_door_thing = make_door_thing(&mut main_door, "main-door-1", "Main Door", false, move |closed| {
-    let ring = ring_clone.clone();
+  let ring = match config.board.ring.then(|| backend.led_strip()) {
+    Some(Ok(led_strip)) => {
+      let mut ring = RgbRing::new(led_strip);
+      for segment in Segment::ALL {
+        if !config.doors.iter().any(|door| door.segment == Some(segment)) {
+          ring.set_segment(segment, RGB8 { r: 0x01, g: 0x01, b: 0x01 });
+        }
+      }
+      Some(Arc::new(Mutex::new(ring)))
+    },
+    Some(Err(err)) => {
+      health.report("LED ring", err);
+      None
+    },
+    None => None,
+  };
 
-    async move {
-      let mut ring = ring.lock().await;
-      ring.set_top_left(closed_to_color(closed));
-      ring.render();
-    }
-  })
-  .await;
-  main_door_thing.write().unwrap().add_available_event(
-    "bell".to_owned(),
-    json!({
-      "description": "The door bell has been rung.",
-      "type": "object",
-      "unit": "",
+  let button_leds = config
+    .buttons
+    .iter()
+    .map(|button| {
+      let led = button.led.as_ref()?;
+
+      match make_rgb_led(&mut *backend, led) {
+        Ok(led) => Some(Arc::new(Mutex::new(led))),
+        Err(err) => {
+          health.report(&format!("{} button LED", config.door(&button.door).unwrap().name), err);
+          None
+        },
+      }
     })
-    .as_object()
-    .unwrap()
-    .to_owned(),
-  );
+    .collect::<Vec<_>>();
 
-  let main_door: Arc<tokio::sync::RwLock<Box<dyn Any + Send + Sync>>> =
-    Arc::new(tokio::sync::RwLock::new(Box::new(main_door)));
+  for door_config in &config.doors {
+    let leds = config
+      .buttons
+      .iter()
+      .zip(&button_leds)
+      .filter(|(button, _)| button.door == door_config.id)
+      .filter_map(|(_, led)| led.clone())
+      .collect::<Vec<_>>();
+    let indicator = Indicator::new(ring.clone(), door_config.segment, leds, health.clone());
 
-  doors.insert(main_door_thing.read().unwrap().get_id(), main_door.clone());
-  things.push(main_door_thing.clone());
+    let (closed_sender, closed) = watch::channel(true);
+    let indicator_clone = indicator.clone();
+    let on_change = move |closed| {
+      closed_sender.send_replace(closed);
+      let indicator = indicator_clone.clone();
+      async move { indicator.show_state(closed).await }
+    };
 
-  let main_door_thing_clone = main_door_thing.clone();
-  door_bell_button
-    .set_async_interrupt(
-      Trigger::Both,
-      Some(Duration::from_millis(50)),
-      on_change_async(move |closed| {
-        let main_door_thing = main_door_thing_clone.clone();
+    let attribution = config
+      .ekey
+      .scanners
+      .values()
+      .any(|door| *door == door_config.id)
+      .then(|| Attribution::new(config.ekey.attribution_window));
+    let guard = intrusion.as_ref().and_then(|intrusion| intrusion.guard(&door_config.id));
 
-        async move {
-          if closed {
-            log::info!("Door bell button pressed.");
+    let mut motion = None;
+    let (door_thing, door): (_, Box<dyn Actuator>) = match door_config.ty {
+      DoorType::Door => {
+        let mut door = match make_door(&mut *backend, door_config) {
+          Ok(door) => door,
+          Err(err) => {
+            health.report(&door_config.name, err);
+            continue
+          },
+        };
+        let door_thing =
+          make_door_thing(&mut door, door_config, &bus, &health, attribution.clone(), guard.clone(), on_change).await;
+        (door_thing, Box::new(door))
+      },
+      DoorType::Garage => {
+        let mut door = match make_garage_door(&mut *backend, door_config) {
+          Ok(door) => door,
+          Err(err) => {
+            health.report(&door_config.name, err);
+            continue
+          },
+        };
+        let door_thing =
+          make_door_thing(&mut door, door_config, &bus, &health, attribution.clone(), guard.clone(), on_change).await;
+        add_motion_property(&door_thing, door.motion());
+        add_open_failed_event(&door_thing, door.open_failed());
+        motion = Some(door.motion());
+        (door_thing, Box::new(door))
+      },
+      DoorType::MotorLock => {
+        let mut door = match make_motor_lock(&mut *backend, door_config) {
+          Ok(door) => door,
+          Err(err) => {
+            health.report(&door_config.name, err);
+            continue
+          },
+        };
+        let door_thing =
+          make_door_thing(&mut door, door_config, &bus, &health, attribution.clone(), guard.clone(), on_change).await;
+        (door_thing, Box::new(door))
+      },
+    };
+    health.add_property(&door_thing);
 
-            let event = Box::new(BaseEvent::new("bell".to_owned(), Some(json!(true))));
-            main_door_thing.write().unwrap().add_event(event);
-          } else {
-            log::info!("Door bell button released.");
-          }
-        }
-      }),
-    )
-    .unwrap();
+    let door = match &guard {
+      Some(guard) => guard.wrap(door),
+      None => door,
+    };
+    let door = match &audit {
+      Some(audit) => audit.wrap(&door_config.id, door),
+      None => door,
+    };
 
-  let mut cellar_door = Door::new(board.cellar_door_open, board.cellar_door_contact);
-  let ring_clone = ring.clone();
-  let cellar_door_thing = make_door_thing(&mut cellar_door, "cellar-door-1", "Cellar Door", false, move |closed| {
-    let ring = ring_clone.clone();
+    if let (Some(intrusion), Some(_)) = (&mut intrusion, &guard) {
+      intrusion.add_door(&door_config.id, door_thing.clone(), indicator.clone());
+    }
 
-    async move {
-      let mut ring = ring.lock().await;
-      ring.set_bottom_right(closed_to_color(closed));
-      ring.render();
+    if config.guests.enabled {
+      Guests::add_event(door_thing.write().unwrap().as_mut());
     }
-  })
-  .await;
-  let cellar_door: Arc<tokio::sync::RwLock<Box<dyn Any + Send + Sync>>> =
-    Arc::new(tokio::sync::RwLock::new(Box::new(cellar_door)));
-
-  doors.insert(cellar_door_thing.read().unwrap().get_id(), cellar_door.clone());
-  things.push(cellar_door_thing.clone());
-
-  let mut garage_door = GarageDoor::new(
-    board.garage_door_2_open,
-    board.garage_door_2_stop,
-    board.garage_door_2_close,
-    board.garage_door_2_contact,
-  );
-  let led_clone = led.clone();
-  let ring_clone = ring.clone();
-  let garage_door_thing = make_door_thing(&mut garage_door, "garage-door-1", "Garage Door", true, move |closed| {
-    let led = led_clone.clone();
-    let ring = ring_clone.clone();
 
-    async move {
-      {
-        let mut ring = ring.lock().await;
-        ring.set_top_right(closed_to_color(closed));
-        ring.render();
-      }
+    let capabilities = door.capabilities();
+    let door: SharedDoor = Arc::new(tokio::sync::RwLock::new(door));
 
-      let mut led = led.lock().await;
+    if capabilities.contains(&Capability::Hold) {
+      let hold_open = HoldOpen::new(door.clone(), door_thing.clone(), indicator.clone(), door_config.max_hold_open());
+      hold_opens.insert(door_thing.read().unwrap().get_id(), hold_open.handle());
+      actix_rt::spawn(hold_open.run());
+    }
 
-      if closed {
-        led.0.set_low();
-        led.1.set_high();
-      } else {
-        led.0.set_high();
-        led.1.set_low();
-      }
-      led.2.set_low();
+    if let Some(left_open) = &door_config.left_open {
+      let left_open =
+        LeftOpen::new(left_open.clone(), door_config.id.clone(), door_thing.clone(), indicator.clone(), closed);
+      actix_rt::spawn(left_open.run());
     }
-  })
-  .await;
-  let garage_door: Arc<tokio::sync::RwLock<Box<dyn Any + Send + Sync>>> =
-    Arc::new(tokio::sync::RwLock::new(Box::new(garage_door)));
-
-  let garage_door_clone = garage_door.clone();
-  let led_clone = led.clone();
-  garage_door_button
-    .set_async_interrupt(
-      Trigger::Both,
-      Some(Duration::from_millis(50)),
-      on_change_async(move |closed| {
-        let led = led_clone.clone();
-        let garage_door = garage_door_clone.clone();
-
-        async move {
-          let mut led = led.lock().await;
-
-          if closed {
-            log::info!("Garage door button pressed.");
-
-            led.0.set_high();
-            led.1.set_high();
-            led.2.set_high();
-
-            let mut garage_door = garage_door.write().await;
-            let garage_door = garage_door.downcast_mut::<GarageDoor>().unwrap();
-
-            if garage_door.is_open() {
-              log::info!("Garage is open, closing.");
-              garage_door.close().await
-            } else {
-              log::info!("Garage is closed, opening.");
-              garage_door.open().await
+
+    if let (Some(auto_close), Some(motion)) = (&door_config.auto_close, motion) {
+      let auto_close = AutoClose::new(auto_close.clone(), door.clone(), door_thing.clone(), motion, indicator);
+      auto_closes.insert(door_config.id.clone(), auto_close.handle());
+      actix_rt::spawn(auto_close.run());
+    }
+
+    if let Some(attribution) = attribution {
+      attributions.insert(door_config.id.clone(), attribution);
+    }
+
+    doors.insert(door_thing.read().unwrap().get_id(), (capabilities, door));
+    things.push(door_thing.clone());
+    thing_ids.push(door_config.id.clone());
+    door_things.insert(door_config.id.clone(), door_thing);
+  }
+
+  let mut buttons = Vec::new();
+
+  for (button, led) in config.buttons.iter().zip(button_leds) {
+    let door_config = config.door(&button.door).unwrap();
+    let door_name = door_config.name.clone();
+    let Some(door_thing) = door_things.get(&door_config.id).cloned() else {
+      log::warn!("{door_name} is unavailable, ignoring its button.");
+      continue
+    };
+
+    let component = match button.ty {
+      ButtonType::Bell => format!("{door_name} bell button"),
+      ButtonType::Toggle => format!("{door_name} button"),
+    };
+    let mut button_pin = match backend.input_pin(button.pin) {
+      Ok(pin) => pin,
+      Err(err) => {
+        health.report(&component, err);
+        continue
+      },
+    };
+
+    let result = match button.ty {
+      ButtonType::Bell => {
+        door_thing.write().unwrap().add_available_event(
+          "bell".to_owned(),
+          json!({
+            "description": "The door bell has been rung.",
+            "type": "object",
+            "unit": "",
+          })
+          .as_object()
+          .unwrap()
+          .to_owned(),
+        );
+
+        button_pin.set_interrupt(
+          Some(Duration::from_millis(50)),
+          bus.on_change(move |closed| {
+            let door_thing = door_thing.clone();
+            let door_name = door_name.clone();
+
+            async move {
+              if closed {
+                log::info!("{door_name} bell button pressed.");
+
+                let event = Box::new(BaseEvent::new("bell".to_owned(), Some(json!(true))));
+                door_thing.write().unwrap().add_event(event);
+              } else {
+                log::info!("{door_name} bell button released.");
+              }
             }
-          } else {
-            log::info!("Garage door button released.");
+          }),
+        )
+      },
+      ButtonType::Toggle => {
+        let (_, door) = doors[&door_thing.read().unwrap().get_id()].clone();
+        let auto_close = auto_closes.get(&door_config.id).cloned();
 
-            led.0.set_high();
-            led.1.set_high();
-            led.2.set_low();
-          }
-        }
-      }),
-    )
-    .unwrap();
+        button_pin.set_interrupt(
+          Some(Duration::from_millis(50)),
+          bus.on_change(move |closed| {
+            let led = led.clone();
+            let door = door.clone();
+            let door_name = door_name.clone();
+            let auto_close = auto_close.clone();
+
+            async move {
+              if closed {
+                log::info!("{door_name} button pressed.");
+
+                if auto_close.is_some_and(|auto_close| auto_close.abort()) {
+                  return
+                }
+
+                if let Some(led) = &led {
+                  let mut led = led.lock().await;
+                  led.red.set_high();
+                  led.green.set_high();
+                  led.blue.set_high();
+                }
+
+                let toggle = async { door.write().await.toggle().await };
+                if let Err(err) = audit::scope("button".to_owned(), toggle).await {
+                  log::error!("Toggling {door_name} failed: {err}");
+                }
+              } else {
+                log::info!("{door_name} button released.");
+
+                if let Some(led) = &led {
+                  let mut led = led.lock().await;
+                  led.red.set_high();
+                  led.green.set_high();
+                  led.blue.set_low();
+                }
+              }
+            }
+          }),
+        )
+      },
+    };
+
+    if let Err(err) = result {
+      health.report(&component, err);
+    }
+
+    buttons.push(button_pin);
+  }
+
+  if let Some(history) = &history {
+    for (id, door_thing) in &door_things {
+      history.watch(id, door_thing);
+    }
+  }
 
-  doors.insert(garage_door_thing.read().unwrap().get_id(), garage_door.clone());
-  things.push(garage_door_thing.clone());
+  // Doors which need to be released when shutting down.
+  let held_doors = doors
+    .values()
+    .filter(|(capabilities, _)| capabilities.contains(&Capability::Hold))
+    .map(|(_, door)| door.clone())
+    .collect::<Vec<_>>();
+
+  let generator = Generator { doors, hold_opens, intrusion: intrusion.as_ref().map(Intrusion::handle) };
+
+  let guests = config
+    .guests
+    .enabled
+    .then(|| Guests::open(&config.guests.path, door_things.clone(), Box::new(generator.clone())).map(web::Data::new));
+  let guests = match guests {
+    Some(Ok(guests)) => Some(guests),
+    Some(Err(err)) => {
+      health.report("Guests", err);
+      None
+    },
+    None => None,
+  };
+
+  let scanners = config
+    .ekey
+    .scanners
+    .iter()
+    .filter_map(|(scanner, door)| {
+      let guard = intrusion.as_ref().and_then(|intrusion| intrusion.guard(door));
+      Some((scanner.clone(), (door.clone(), door_things.get(door)?.clone(), attributions.get(door)?.clone(), guard)))
+    })
+    .collect::<HashMap<_, _>>();
+
+  let mut mqtt = config.mqtt.clone().map(|mqtt| {
+    let discovery = if mqtt.discovery { discovery::configs(&config, &mqtt) } else { Vec::new() };
+    Mqtt::new(mqtt, discovery, Box::new(generator.clone()), health.clone())
+  });
+  if let Some(mqtt) = &mut mqtt {
+    for (id, door_thing) in &door_things {
+      mqtt.add_thing(id, door_thing.clone());
+    }
+  }
+
+  if let Some(intrusion) = intrusion {
+    if let Some(history) = &history {
+      history.watch("house", &intrusion.thing());
+    }
+
+    if let Some(mqtt) = &mut mqtt {
+      mqtt.add_thing("house", intrusion.thing());
+    }
+
+    things.push(intrusion.thing());
+    thing_ids.push("house".to_owned());
+    actix_rt::spawn(intrusion.run());
+  }
 
-  let generator = Generator { doors };
+  if let Some(mqtt) = mqtt {
+    actix_rt::spawn(mqtt.run());
+  }
 
+  let ekey_port = config.ekey.port;
+  let ekey_history = history.clone();
   let ekey_receiver = async {
-    let socket: UdpSocket = UdpSocket::bind("0.0.0.0:56000").await?;
+    let socket: UdpSocket = UdpSocket::bind(("0.0.0.0", ekey_port)).await?;
     let mut buf: [u8; 64] = [0; 64];
     loop {
       let (size, _) = socket.recv_from(&mut buf).await?;
@@ -342,22 +912,34 @@ async fn main() {
             );
 
             let value = serde_json::value::to_value(&packet).unwrap();
-            let event = Box::new(BaseEvent::new("finger_scan".to_owned(), Some(value)));
 
-            match packet.finger_scanner_name() {
-              "HT" => {
-                let main_door = &mut *main_door_thing.write().unwrap();
-                main_door.add_event(event);
-              },
-              "KT" => {
-                let cellar_door = &mut *cellar_door_thing.write().unwrap();
-                cellar_door.add_event(event);
+            match scanners.get(packet.finger_scanner_name()) {
+              Some((door, door_thing, attribution, guard)) => {
+                if packet.action() == ekey::Action::Open {
+                  let user =
+                    packet.user_name().map(str::to_owned).unwrap_or_else(|| format!("user {}", packet.user_id()));
+
+                  // The finger scanner opens the door itself, so only the scan can be recorded.
+                  if let Some(audit) = &audit {
+                    audit.record_as(door, "finger_scan", format!("ekey:{user}"), "ok".to_owned());
+                  }
+                  attribution.scanned(user);
+
+                  if let Some(guard) = guard {
+                    guard.authorize();
+                  }
+                }
+
+                let event = Box::new(BaseEvent::new("finger_scan".to_owned(), Some(value)));
+                door_thing.write().unwrap().add_event(event)
               },
-              "GT" => {
-                let garage_door = &mut *garage_door_thing.write().unwrap();
-                garage_door.add_event(event);
+              None => {
+                log::warn!("Unknown finger scanner: {}", packet.finger_scanner_name());
+
+                if let Some(history) = &ekey_history {
+                  history.record(history::UNKNOWN_SCANNER, Kind::Event, "finger_scan", value);
+                }
               },
-              finger_scanner_name => log::warn!("Unknown finger scanner: {finger_scanner_name}"),
             }
           },
           Err(err) => log::error!("Invalid EKEY message format: {err:?}"),
@@ -370,6 +952,26 @@ async fn main() {
     Ok::<_, io::Error>(())
   };
 
+  let auth = match &config.auth {
+    Some(auth) => {
+      let admin_client_certificate = config.tls.as_ref().is_some_and(|tls| tls.client_ca.is_some());
+      Some(web::Data::new(Auth::new(auth, thing_ids, admin_client_certificate)))
+    },
+    None => {
+      log::warn!("No `auth` section configured, anyone can access the API.");
+      None
+    },
+  };
+
+  let tls = match config.tls.clone().map(|tls| tls::server_config(tls, health.clone())).transpose() {
+    Ok(tls) => tls,
+    Err(err) => {
+      log::error!("Failed to load TLS certificate: {err}");
+      process::exit(1)
+    },
+  };
+  let tls_enabled = tls.is_some();
+
   let webthing_server = {
     let mut server = WebThingServer::new(
       ThingsType::Multiple(things, "DoorServer".to_owned()),
@@ -381,15 +983,106 @@ async fn main() {
       Some(true),
     );
 
-    log::info!("Starting WebThing server on port {port}…");
-    server.start(None)
+    // Build the HTTP server here instead of using `WebThingServer::start`,
+    // so that the history endpoint is matched before the routes of the things.
+    let things_config = server.make_config();
+    let server = HttpServer::new(move || {
+      let history = history.clone();
+      let auth = auth.clone();
+      let guests = guests.clone();
+
+      App::new()
+        .wrap(middleware::from_fn(auth::authorize))
+        // Guest access links contain their token.
+        .wrap(middleware::Logger::default().exclude_regex("^/guest/"))
+        .wrap(
+          middleware::DefaultHeaders::new()
+            .add(("Access-Control-Allow-Origin", "*"))
+            .add(("Access-Control-Allow-Methods", "GET, HEAD, PUT, POST, DELETE, OPTIONS"))
+            .add(("Access-Control-Allow-Headers", "Origin, Content-Type, Accept, X-Requested-With, Authorization")),
+        )
+        .configure(|config| {
+          if let Some(auth) = auth {
+            auth::configure(config, auth)
+          }
+        })
+        .configure(|config| {
+          if let Some(guests) = guests {
+            guests::configure(config, guests)
+          }
+        })
+        .configure(|config| {
+          if let Some(history) = history {
+            history::configure(config, history)
+          }
+        })
+        .configure(&things_config)
+    })
+    .on_connect(tls::on_connect);
+
+    let server = match tls {
+      Some(tls) => server.bind_rustls_0_23(("0.0.0.0", port), tls),
+      None => server.bind(("0.0.0.0", port)),
+    };
+    let server = match server {
+      Ok(server) => server,
+      Err(err) => {
+        log::error!("Failed to listen on port {port}: {err}");
+        process::exit(1)
+      },
+    };
+
+    log::info!("Starting WebThing server on port {port}{}…", if tls_enabled { " with TLS" } else { "" });
+    server.run()
   };
 
-  let signal = async { signal::ctrl_c().await.unwrap() };
+  // Advertise the server like `WebThingServer::start` does.
+  let _mdns_service = match libmdns::Responder::new() {
+    Ok(responder) => {
+      let txt: &[&str] = if tls_enabled { &["path=/", "tls=1"] } else { &["path=/"] };
+      Some(responder.register("_webthing._tcp".to_owned(), "DoorServer".to_owned(), port, txt))
+    },
+    Err(err) => {
+      health.report("mDNS", err);
+      None
+    },
+  };
+
+  // Service managers stop the server with SIGTERM, so wait for it as well as for SIGINT.
+  let mut terminate = signal::unix::signal(SignalKind::terminate()).unwrap();
+  let signal = async {
+    tokio::select! {
+      result = signal::ctrl_c() => result.unwrap(),
+      _ = terminate.recv() => log::info!("SIGTERM received, stopping."),
+    }
+  };
+
+  let simulation = async {
+    match simulation {
+      Some(simulation) => {
+        let control_port = env::var("SIMULATION_PORT")
+          .map(|s| s.parse::<u16>().expect("Simulation port is invalid"))
+          .unwrap_or(port + 1);
+        simulation.run(control_port).await
+      },
+      None => future::pending().await,
+    }
+  };
 
   tokio::select! {
     _ = signal => (),
     _ = ekey_receiver => (),
     _ = webthing_server => (),
+    result = simulation => {
+      if let Err(err) = result {
+        log::error!("Simulation failed: {err}");
+      }
+    },
   }
+
+  for door in held_doors {
+    let _ = audit::scope("shutdown".to_owned(), async { door.write().await.release().await }).await;
+  }
+
+  drop(buttons);
 }
@@ -0,0 +1,137 @@
+use std::{sync::Arc, time::Duration};
+
+use actix_rt::time::{sleep, timeout};
+use serde::Serialize;
+use tokio::sync::watch;
+
+use super::*;
+use crate::hal::{InputPin, TriggerPin};
+
+/// How often the bolt sensor is checked while the motor is running.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LockState {
+  Locked,
+  Unlocked,
+  /// The bolt did not reach its end position in time.
+  Jammed,
+}
+
+/// A door with a motorised deadbolt, driven by separate lock and unlock outputs.
+///
+/// The bolt sensor is pulled low while the bolt is thrown.
+#[derive(Debug)]
+pub struct MotorLock {
+  trigger_lock: Box<dyn TriggerPin>,
+  trigger_unlock: Box<dyn TriggerPin>,
+  contact: Box<dyn InputPin>,
+  bolt: Box<dyn InputPin>,
+  lock_state: Arc<watch::Sender<LockState>>,
+  travel_time: Duration,
+}
+
+impl MotorLock {
+  pub fn new(
+    mut trigger_lock: Box<dyn TriggerPin>,
+    mut trigger_unlock: Box<dyn TriggerPin>,
+    contact: Box<dyn InputPin>,
+    bolt: Box<dyn InputPin>,
+    travel_time: Duration,
+  ) -> Self {
+    trigger_lock.set_inactive();
+    trigger_unlock.set_inactive();
+
+    let (lock_state, _) = watch::channel(if bolt.is_low() { LockState::Locked } else { LockState::Unlocked });
+
+    Self { trigger_lock, trigger_unlock, contact, bolt, lock_state: Arc::new(lock_state), travel_time }
+  }
+
+  /// Run the motor until the bolt reaches the given position, or mark the lock as jammed.
+  async fn drive(&mut self, lock: bool) -> Result<()> {
+    let trigger = if lock { &mut self.trigger_lock } else { &mut self.trigger_unlock };
+    let bolt = &self.bolt;
+
+    trigger.set_active();
+    let reached = timeout(self.travel_time, async {
+      while bolt.is_low() != lock {
+        sleep(POLL_INTERVAL).await;
+      }
+    })
+    .await
+    .is_ok();
+    trigger.set_inactive();
+
+    if reached {
+      self.lock_state.send_replace(if lock { LockState::Locked } else { LockState::Unlocked });
+      Ok(())
+    } else {
+      self.lock_state.send_replace(LockState::Jammed);
+      Err(Error::Jammed)
+    }
+  }
+
+  pub async fn lock(&mut self) -> Result<()> {
+    if self.is_open() {
+      return Err(Error::DoorOpen)
+    }
+
+    self.drive(true).await
+  }
+
+  pub async fn unlock(&mut self) -> Result<()> {
+    self.drive(false).await
+  }
+}
+
+impl Actuator for MotorLock {
+  fn capabilities(&self) -> &'static [Capability] {
+    &[Capability::Open, Capability::Close, Capability::Toggle]
+  }
+
+  fn open(&mut self, _pulse: Option<Duration>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(self.unlock())
+  }
+
+  fn close(&mut self, _pulse: Option<Duration>) -> BoxFuture<'_, Result<()>> {
+    Box::pin(self.lock())
+  }
+
+  fn toggle(&mut self) -> BoxFuture<'_, Result<()>> {
+    Box::pin(async {
+      if *self.lock_state.borrow() == LockState::Locked { self.unlock().await } else { self.lock().await }
+    })
+  }
+}
+
+impl StatefulDoor for MotorLock {
+  fn on_change<C, F>(&mut self, bus: &EventBus, callback: C) -> Result<()>
+  where
+    F: Future + 'static,
+    C: (FnMut(bool) -> F) + 'static,
+  {
+    let lock_state = self.lock_state.clone();
+    self.bolt.set_interrupt(
+      Some(Duration::from_millis(50)),
+      bus.on_change(move |locked| {
+        lock_state.send_replace(if locked { LockState::Locked } else { LockState::Unlocked });
+        async {}
+      }),
+    )?;
+
+    self.contact.set_interrupt(Some(Duration::from_millis(50)), bus.on_change(callback))
+  }
+
+  fn is_closed(&self) -> bool {
+    self.contact.is_low()
+  }
+
+  fn is_open(&self) -> bool {
+    !self.is_closed()
+  }
+
+  fn lock_state(&self) -> Option<watch::Receiver<LockState>> {
+    Some(self.lock_state.subscribe())
+  }
+}
@@ -0,0 +1,223 @@
+use std::{
+  collections::HashMap,
+  sync::{Arc, RwLock},
+  time::Duration,
+};
+
+use actix_rt::time::sleep;
+use rumqttc::{AsyncClient, Event, EventLoop, LastWill, MqttOptions, Packet, Publish, QoS};
+use webthing::{Thing, server::ActionGenerator};
+
+use door_server::config::MqttConfig;
+
+use crate::{
+  action, audit,
+  health::Health,
+  subscription::{Kind, subscribe},
+};
+
+/// ID under which the MQTT bridge subscribes to the message queues of things.
+const SUBSCRIBER: &str = "mqtt";
+
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Strings are published as is, everything else as JSON.
+fn payload(value: &serde_json::Value) -> Vec<u8> {
+  match value {
+    serde_json::Value::String(s) => s.clone().into_bytes(),
+    value => value.to_string().into_bytes(),
+  }
+}
+
+/// Publishes properties and events of things to an MQTT broker and performs actions sent to it.
+///
+/// Properties are retained at `{prefix}/{id}/{property}`, events are sent to `{prefix}/{id}/event/{event}` and
+/// actions are accepted at `{prefix}/{id}/command`. `{prefix}/status` is `online` while connected, `offline` otherwise.
+pub struct Mqtt {
+  prefix: String,
+  client: AsyncClient,
+  event_loop: EventLoop,
+  things: HashMap<String, Arc<RwLock<Box<dyn Thing>>>>,
+  discovery: Vec<(String, serde_json::Value)>,
+  discovery_status: Option<String>,
+  generator: Box<dyn ActionGenerator>,
+  health: Health,
+}
+
+impl Mqtt {
+  pub fn new(
+    config: MqttConfig,
+    discovery: Vec<(String, serde_json::Value)>,
+    generator: Box<dyn ActionGenerator>,
+    health: Health,
+  ) -> Self {
+    let status = format!("{}/status", config.prefix);
+    let discovery_status = config.discovery.then(|| format!("{}/status", config.discovery_prefix));
+
+    let mut options = MqttOptions::new(config.client_id, config.host, config.port);
+    options.set_keep_alive(KEEP_ALIVE);
+    options.set_last_will(LastWill::new(status, "offline", QoS::AtLeastOnce, true));
+    if let Some(username) = config.username {
+      options.set_credentials(username, config.password.unwrap_or_default());
+    }
+
+    let (client, event_loop) = AsyncClient::new(options, 64);
+
+    Self {
+      prefix: config.prefix,
+      client,
+      event_loop,
+      things: HashMap::new(),
+      discovery,
+      discovery_status,
+      generator,
+      health,
+    }
+  }
+
+  /// Publish all property changes and events of a thing.
+  ///
+  /// Must be called after all events have been added to the thing.
+  pub fn add_thing(&mut self, id: &str, thing: Arc<RwLock<Box<dyn Thing>>>) {
+    let mut messages = subscribe(SUBSCRIBER, &thing);
+    let client = self.client.clone();
+    let prefix = format!("{}/{id}", self.prefix);
+
+    actix_rt::spawn(async move {
+      while let Some(message) = messages.recv().await {
+        let (topic, retain) = match message.kind {
+          Kind::Property => (format!("{prefix}/{}", message.name), true),
+          Kind::Event => (format!("{prefix}/event/{}", message.name), false),
+          Kind::Action => continue,
+        };
+
+        if client.publish(topic, QoS::AtLeastOnce, retain, payload(&message.value)).await.is_err() {
+          return
+        }
+      }
+    });
+
+    self.things.insert(id.to_owned(), thing);
+  }
+
+  /// Publish the Home Assistant discovery configs.
+  fn discover(&self) {
+    let client = self.client.clone();
+    let discovery = self.discovery.clone();
+
+    actix_rt::spawn(async move {
+      for (topic, config) in discovery {
+        if let Err(err) = client.publish(topic, QoS::AtLeastOnce, true, config.to_string()).await {
+          log::error!("Publishing MQTT discovery failed: {err}");
+          return
+        }
+      }
+    });
+  }
+
+  /// Subscribe to commands and publish the current state, since the broker may have lost it.
+  fn connected(&self) {
+    self.discover();
+
+    let client = self.client.clone();
+    let prefix = self.prefix.clone();
+    let discovery_status = self.discovery_status.clone();
+    let things = self.things.clone();
+
+    actix_rt::spawn(async move {
+      let result = async {
+        client.subscribe(format!("{prefix}/+/command"), QoS::AtLeastOnce).await?;
+        // Home Assistant loses discovery configs when it restarts without a persistent broker.
+        if let Some(discovery_status) = discovery_status {
+          client.subscribe(discovery_status, QoS::AtLeastOnce).await?;
+        }
+        client.publish(format!("{prefix}/status"), QoS::AtLeastOnce, true, "online").await?;
+
+        for (id, thing) in things {
+          let properties = thing.read().unwrap().get_properties();
+          for (name, value) in properties {
+            client.publish(format!("{prefix}/{id}/{name}"), QoS::AtLeastOnce, true, payload(&value)).await?;
+          }
+        }
+
+        Ok::<_, rumqttc::ClientError>(())
+      };
+
+      if let Err(err) = result.await {
+        log::error!("Publishing MQTT state failed: {err}");
+      }
+    });
+  }
+
+  /// Perform the action in a command, which is either just the name of the action
+  /// or a JSON object like the body of `POST /things/{id}/actions`.
+  fn command(&self, publish: &Publish) {
+    let Some(id) = publish
+      .topic
+      .strip_prefix(&self.prefix)
+      .and_then(|topic| topic.strip_prefix('/'))
+      .and_then(|topic| topic.strip_suffix("/command"))
+    else {
+      return
+    };
+
+    let Some(thing) = self.things.get(id) else {
+      log::warn!("Ignoring MQTT command for unknown thing {id}.");
+      return
+    };
+
+    let Ok(payload) = str::from_utf8(&publish.payload) else {
+      log::warn!("Ignoring invalid MQTT command for {id}.");
+      return
+    };
+    let payload = payload.trim();
+
+    let (name, input) = if payload.starts_with('{') {
+      let request = serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(payload);
+      match request.ok().filter(|request| request.len() == 1).and_then(|request| request.into_iter().next()) {
+        Some((name, params)) => (name, params.get("input").cloned()),
+        None => {
+          log::warn!("Ignoring invalid MQTT command for {id}: {payload}");
+          return
+        },
+      }
+    } else {
+      (payload.to_owned(), None)
+    };
+
+    log::info!("Received MQTT command {name} for {id}.");
+
+    let result =
+      audit::sync_scope("mqtt".to_owned(), || action::request(self.generator.as_ref(), thing, &name, input.as_ref()));
+    if let Err(err) = result {
+      log::warn!("Rejecting MQTT command {name} for {id}: {err}");
+    }
+  }
+
+  pub async fn run(mut self) {
+    loop {
+      match self.event_loop.poll().await {
+        Ok(Event::Incoming(Packet::ConnAck(_))) => {
+          log::info!("Connected to MQTT broker.");
+          self.health.resolve("MQTT");
+          self.connected();
+        },
+        Ok(Event::Incoming(Packet::Publish(publish))) => {
+          if Some(&publish.topic) == self.discovery_status.as_ref() {
+            if *publish.payload == *b"online" {
+              self.discover();
+            }
+          } else {
+            self.command(&publish)
+          }
+        },
+        Ok(_) => (),
+        Err(err) => {
+          self.health.report("MQTT", err);
+          sleep(RECONNECT_DELAY).await;
+        },
+      }
+    }
+  }
+}
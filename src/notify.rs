@@ -0,0 +1,24 @@
+use tokio::process;
+
+use door_server::config::NotifyConfig;
+
+/// Send a notification about an event of a door to all given sinks.
+pub fn notify(sinks: &[NotifyConfig], event: &str, door: &str, name: &str) {
+  for sink in sinks {
+    match sink {
+      NotifyConfig::Command { command } => {
+        let program = command[0].clone();
+        let mut process = process::Command::new(&program);
+        process.args(&command[1..]).env("EVENT", event).env("DOOR_ID", door).env("DOOR_NAME", name);
+
+        actix_rt::spawn(async move {
+          match process.status().await {
+            Ok(status) if status.success() => (),
+            Ok(status) => log::error!("Notification command {program} failed with {status}."),
+            Err(err) => log::error!("Running notification command {program} failed: {err}"),
+          }
+        });
+      },
+    }
+  }
+}
@@ -0,0 +1,322 @@
+use std::{
+  collections::HashMap,
+  io,
+  sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+  },
+  time::{Duration, Instant},
+};
+
+use actix_rt::time::sleep;
+use tokio::{
+  io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+  net::{TcpListener, TcpStream, UdpSocket},
+  sync::watch,
+};
+
+use door_server::{
+  config::{ButtonConfig, ButtonType, Config, DoorConfig, DoorType},
+  hal::{Level, MemoryBackend, MemoryPin},
+};
+
+/// How long a simulated person keeps a door open after it was unlocked.
+const DOOR_OPEN_TIME: Duration = Duration::from_secs(5);
+
+/// How long a simulated motor lock takes to throw or retract its bolt.
+const BOLT_TRAVEL_TIME: Duration = Duration::from_secs(1);
+
+/// How long buttons are held down when pressed via the control socket.
+const BUTTON_PRESS_TIME: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Direction {
+  Up,
+  Down,
+}
+
+/// Virtual doors, bell and buttons reacting to the pins of a [`MemoryBackend`].
+pub struct Simulation {
+  backend: MemoryBackend,
+  doors: Vec<DoorConfig>,
+  buttons: Vec<ButtonConfig>,
+  ekey_port: u16,
+  jammed: HashMap<String, Arc<AtomicBool>>,
+}
+
+impl Simulation {
+  pub fn new(backend: MemoryBackend, config: &Config) -> Self {
+    let mut jammed = HashMap::new();
+
+    for door in &config.doors {
+      backend.pin(door.pins.contact).set_level(Level::Low);
+
+      if let Some(bolt) = door.pins.bolt {
+        backend.pin(bolt).set_level(Level::Low);
+        jammed.insert(door.id.clone(), Arc::new(AtomicBool::new(false)));
+      }
+    }
+
+    Self { backend, doors: config.doors.clone(), buttons: config.buttons.clone(), ekey_port: config.ekey.port, jammed }
+  }
+
+  pub fn backend(&self) -> MemoryBackend {
+    self.backend.clone()
+  }
+
+  pub async fn run(self, control_port: u16) -> io::Result<()> {
+    for door_config in &self.doors {
+      let pin = |n| self.backend.pin(n);
+      let pins = &door_config.pins;
+
+      match door_config.ty {
+        DoorType::Door => {
+          actix_rt::spawn(door(pin(pins.open), pin(pins.contact)));
+        },
+        DoorType::Garage => {
+          actix_rt::spawn(garage_door(
+            pin(pins.open),
+            pin(pins.stop.unwrap()),
+            pin(pins.close.unwrap()),
+            pin(pins.contact),
+            door_config.travel_time(),
+          ));
+        },
+        DoorType::MotorLock => {
+          actix_rt::spawn(motor_lock(
+            pin(pins.close.unwrap()),
+            pin(pins.open),
+            pin(pins.bolt.unwrap()),
+            self.jammed[&door_config.id].clone(),
+          ));
+        },
+      }
+    }
+
+    let listener = TcpListener::bind(("127.0.0.1", control_port)).await?;
+    log::info!("Listening for simulation commands on port {control_port}…");
+
+    let simulation = Arc::new(self);
+
+    loop {
+      let (stream, _) = listener.accept().await?;
+      let simulation = simulation.clone();
+
+      actix_rt::spawn(async move {
+        if let Err(err) = simulation.handle_control_connection(stream).await {
+          log::error!("Simulation control connection failed: {err}");
+        }
+      });
+    }
+  }
+
+  fn contact_pin(&self, door: &str) -> Option<MemoryPin> {
+    let door = self.doors.iter().find(|d| d.id == door)?;
+    Some(self.backend.pin(door.pins.contact))
+  }
+
+  fn button_pins(&self, ty: ButtonType, door: Option<&str>) -> Vec<MemoryPin> {
+    self
+      .buttons
+      .iter()
+      .filter(|button| button.ty == ty && door.is_none_or(|door| button.door == door))
+      .map(|button| self.backend.pin(button.pin))
+      .collect()
+  }
+
+  async fn press(&self, ty: ButtonType, door: Option<&str>) -> Result<(), String> {
+    let pins = self.button_pins(ty, door);
+
+    if pins.is_empty() {
+      return Err("no such button".into())
+    }
+
+    for pin in &pins {
+      pin.set_level(Level::Low);
+    }
+    sleep(BUTTON_PRESS_TIME).await;
+    for pin in &pins {
+      pin.set_level(Level::High);
+    }
+
+    Ok(())
+  }
+
+  async fn handle_control_connection(&self, stream: TcpStream) -> io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Some(line) = lines.next_line().await? {
+      let mut args = line.split_whitespace();
+
+      let response = match (args.next(), args.next(), args.next()) {
+        (None, _, _) => continue,
+        (Some("bell"), door, None) => self.press(ButtonType::Bell, door).await,
+        (Some("button"), door, None) => self.press(ButtonType::Toggle, door).await,
+        (Some("contact"), Some(door), state) => match self.contact_pin(door) {
+          Some(contact) => {
+            let level = match state {
+              Some("open") => Ok(Level::High),
+              Some("closed") => Ok(Level::Low),
+              Some("toggle") | None => Ok(if contact.level() == Level::Low { Level::High } else { Level::Low }),
+              Some(state) => Err(format!("unknown contact state: {state}")),
+            };
+
+            level.map(|level| contact.set_level(level))
+          },
+          None => Err(format!("unknown door: {door}")),
+        },
+        (Some("jam"), Some(door), state) => match self.jammed.get(door) {
+          Some(jammed) => {
+            let value = match state {
+              Some("on") | None => Ok(true),
+              Some("off") => Ok(false),
+              Some(state) => Err(format!("unknown jam state: {state}")),
+            };
+
+            value.map(|value| jammed.store(value, Ordering::SeqCst))
+          },
+          None => Err(format!("unknown motor lock: {door}")),
+        },
+        (Some("ekey"), Some(_), _) => {
+          let packet = line.trim_start().trim_start_matches("ekey").trim_start();
+          let socket = UdpSocket::bind("127.0.0.1:0").await?;
+          socket.send_to(packet.as_bytes(), ("127.0.0.1", self.ekey_port)).await?;
+          Ok(())
+        },
+        (Some("status"), None, _) => {
+          let status = self
+            .doors
+            .iter()
+            .map(|door| {
+              let closed = self.backend.pin(door.pins.contact).level() == Level::Low;
+              let bolt = match door.pins.bolt.map(|bolt| self.backend.pin(bolt).level()) {
+                Some(Level::Low) => " (locked)",
+                Some(Level::High) => " (unlocked)",
+                None => "",
+              };
+              format!("{}: {}{bolt}", door.id, if closed { "closed" } else { "open" })
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+          writer.write_all(format!("{status}\n").as_bytes()).await?;
+          Ok(())
+        },
+        _ => Err(format!("invalid command: {line}")),
+      };
+
+      match response {
+        Ok(()) => writer.write_all(b"ok\n").await?,
+        Err(err) => writer.write_all(format!("error: {err}\n").as_bytes()).await?,
+      }
+    }
+
+    Ok(())
+  }
+}
+
+/// Wait until a trigger pin is pulled low.
+async fn pressed(pin: &mut watch::Receiver<Level>) {
+  loop {
+    if pin.changed().await.is_err() {
+      return std::future::pending().await;
+    }
+
+    if *pin.borrow_and_update() == Level::Low {
+      return;
+    }
+  }
+}
+
+async fn door(trigger_open: MemoryPin, contact: MemoryPin) {
+  let mut trigger_open = trigger_open.subscribe();
+
+  loop {
+    pressed(&mut trigger_open).await;
+
+    log::info!("Simulated door unlocked, opening it.");
+    contact.set_level(Level::High);
+    sleep(DOOR_OPEN_TIME).await;
+    log::info!("Closing simulated door.");
+    contact.set_level(Level::Low);
+  }
+}
+
+async fn garage_door(
+  trigger_open: MemoryPin,
+  trigger_stop: MemoryPin,
+  trigger_close: MemoryPin,
+  contact: MemoryPin,
+  travel_time: Duration,
+) {
+  let mut trigger_open = trigger_open.subscribe();
+  let mut trigger_stop = trigger_stop.subscribe();
+  let mut trigger_close = trigger_close.subscribe();
+
+  // Distance from the closed position, i.e. `travel_time` means fully open.
+  let mut position = if contact.level() == Level::Low { Duration::ZERO } else { travel_time };
+  let mut direction = None;
+
+  loop {
+    let remaining = match direction {
+      Some(Direction::Up) => travel_time - position,
+      Some(Direction::Down) => position,
+      None => Duration::MAX,
+    };
+    let started = Instant::now();
+
+    let command = tokio::select! {
+      _ = pressed(&mut trigger_open) => Some(Some(Direction::Up)),
+      _ = pressed(&mut trigger_close) => Some(Some(Direction::Down)),
+      _ = pressed(&mut trigger_stop) => Some(None),
+      _ = sleep(remaining), if direction.is_some() => None,
+    };
+
+    let elapsed = started.elapsed();
+    position = match direction {
+      Some(Direction::Up) => (position + elapsed).min(travel_time),
+      Some(Direction::Down) => position.saturating_sub(elapsed),
+      None => position,
+    };
+
+    direction = match command {
+      Some(direction) => {
+        log::info!("Simulated garage door received command {direction:?}.");
+        direction
+      },
+      None => {
+        log::info!("Simulated garage door reached end position.");
+        None
+      },
+    };
+
+    if direction == Some(Direction::Up) && position == travel_time
+      || direction == Some(Direction::Down) && position.is_zero()
+    {
+      direction = None;
+    }
+
+    contact.set_level(if position.is_zero() && direction != Some(Direction::Up) { Level::Low } else { Level::High });
+  }
+}
+
+async fn motor_lock(trigger_lock: MemoryPin, trigger_unlock: MemoryPin, bolt: MemoryPin, jammed: Arc<AtomicBool>) {
+  let mut trigger_lock = trigger_lock.subscribe();
+  let mut trigger_unlock = trigger_unlock.subscribe();
+
+  loop {
+    let level = tokio::select! {
+      _ = pressed(&mut trigger_lock) => Level::Low,
+      _ = pressed(&mut trigger_unlock) => Level::High,
+    };
+
+    sleep(BOLT_TRAVEL_TIME).await;
+
+    if jammed.load(Ordering::SeqCst) {
+      log::info!("Simulated motor lock is jammed.");
+    } else {
+      log::info!("Simulated motor lock {}.", if level == Level::Low { "locked" } else { "unlocked" });
+      bolt.set_level(level);
+    }
+  }
+}
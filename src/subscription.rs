@@ -0,0 +1,106 @@
+use std::{
+  sync::{Arc, RwLock},
+  time::Duration,
+};
+
+use actix_rt::time::interval;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use webthing::Thing;
+
+/// How often the message queues are drained, same as for websockets.
+const DRAIN_INTERVAL: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+  Property,
+  Action,
+  Event,
+}
+
+impl Kind {
+  pub const ALL: [Self; 3] = [Self::Property, Self::Action, Self::Event];
+
+  pub fn as_str(self) -> &'static str {
+    match self {
+      Self::Property => "property",
+      Self::Action => "action",
+      Self::Event => "event",
+    }
+  }
+}
+
+/// A property change, action status change or event of a thing.
+#[derive(Debug)]
+pub struct Message {
+  pub time: DateTime<Utc>,
+  pub kind: Kind,
+  pub name: String,
+  pub value: serde_json::Value,
+}
+
+impl Message {
+  /// Parse a message from the message queue of a thing, as sent to websockets.
+  fn parse(message: &str) -> Option<Self> {
+    let message: serde_json::Value = serde_json::from_str(message).ok()?;
+
+    let kind = match message["messageType"].as_str()? {
+      "propertyStatus" => Kind::Property,
+      "actionStatus" => Kind::Action,
+      "event" => Kind::Event,
+      _ => return None,
+    };
+    let (name, value) = message["data"].as_object()?.iter().next()?;
+
+    let (time, value) = match kind {
+      Kind::Event => (
+        value["timestamp"].as_str().and_then(|time| DateTime::parse_from_rfc3339(time).ok()).map(|time| time.to_utc()),
+        value.get("data").cloned().unwrap_or_default(),
+      ),
+      _ => (None, value.clone()),
+    };
+
+    Some(Self { time: time.unwrap_or_else(Utc::now), kind, name: name.clone(), value })
+  }
+}
+
+/// Receive all property changes, action status changes and events of a thing.
+///
+/// Must be called after all events have been added to the thing.
+pub fn subscribe(subscriber: &str, thing: &Arc<RwLock<Box<dyn Thing>>>) -> mpsc::UnboundedReceiver<Message> {
+  {
+    let mut thing = thing.write().unwrap();
+    thing.add_subscriber(subscriber.to_owned());
+
+    let description = thing.as_thing_description();
+    let events =
+      description.get("events").and_then(|events| events.as_object()).into_iter().flat_map(|events| events.keys());
+    for event in events {
+      thing.add_event_subscriber(event.clone(), subscriber.to_owned());
+    }
+  }
+
+  let (sender, receiver) = mpsc::unbounded_channel();
+  let subscriber = subscriber.to_owned();
+  let thing = thing.clone();
+
+  actix_rt::spawn(async move {
+    let mut interval = interval(DRAIN_INTERVAL);
+
+    loop {
+      interval.tick().await;
+
+      let messages = thing.write().unwrap().drain_queue(subscriber.clone()).into_iter().flatten().collect::<Vec<_>>();
+      for message in messages.iter().filter_map(|message| Message::parse(message)) {
+        if sender.send(message).is_err() {
+          thing.write().unwrap().remove_subscriber(subscriber);
+          return
+        }
+      }
+    }
+  });
+
+  receiver
+}
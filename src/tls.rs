@@ -0,0 +1,113 @@
+use std::{
+  any::Any,
+  error::Error,
+  fs,
+  path::Path,
+  sync::{Arc, RwLock},
+  time::{Duration, SystemTime},
+};
+
+use actix_rt::{net::TcpStream, time::interval};
+use actix_tls::accept::rustls_0_23::TlsStream;
+use actix_web::dev::Extensions;
+use rustls::{
+  RootCertStore, ServerConfig,
+  crypto::{CryptoProvider, ring},
+  pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+  server::{ClientHello, ResolvesServerCert, WebPkiClientVerifier},
+  sign::CertifiedKey,
+};
+
+use door_server::config::TlsConfig;
+
+use crate::health::Health;
+
+/// How often the certificate and key files are checked for changes.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Marks connections with a client certificate verified against `tls.client_ca`.
+#[derive(Debug, Clone, Copy)]
+pub struct ClientCertificate;
+
+/// Record whether a connection has a verified client certificate.
+pub fn on_connect(connection: &dyn Any, data: &mut Extensions) {
+  if let Some(stream) = connection.downcast_ref::<TlsStream<TcpStream>>()
+    && stream.get_ref().1.peer_certificates().is_some_and(|certificates| !certificates.is_empty())
+  {
+    data.insert(ClientCertificate);
+  }
+}
+
+fn load(config: &TlsConfig, provider: &CryptoProvider) -> Result<CertifiedKey, Box<dyn Error>> {
+  let certificates = CertificateDer::pem_file_iter(&config.cert)?.collect::<Result<Vec<_>, _>>()?;
+  let key = PrivateKeyDer::from_pem_file(&config.key)?;
+  Ok(CertifiedKey::from_der(certificates, key, provider)?)
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+  fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Always presents the most recently loaded certificate.
+#[derive(Debug)]
+struct Reloading {
+  key: RwLock<Arc<CertifiedKey>>,
+}
+
+impl ResolvesServerCert for Reloading {
+  fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+    Some(self.key.read().unwrap().clone())
+  }
+}
+
+/// Build the TLS configuration, reloading the certificate and key whenever their files change.
+pub fn server_config(config: TlsConfig, health: Health) -> Result<ServerConfig, Box<dyn Error>> {
+  let provider = Arc::new(ring::default_provider());
+
+  let resolver = Arc::new(Reloading { key: RwLock::new(Arc::new(load(&config, &provider)?)) });
+
+  let builder = ServerConfig::builder_with_provider(provider.clone()).with_safe_default_protocol_versions()?;
+  let builder = match &config.client_ca {
+    Some(client_ca) => {
+      let mut roots = RootCertStore::empty();
+      for certificate in CertificateDer::pem_file_iter(client_ca)? {
+        roots.add(certificate?)?;
+      }
+
+      // Client certificates are only required for admin tokens, which is checked by `auth`.
+      let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+        .allow_unauthenticated()
+        .build()?;
+      builder.with_client_cert_verifier(verifier)
+    },
+    None => builder.with_no_client_auth(),
+  };
+
+  let reloading = resolver.clone();
+  actix_rt::spawn(async move {
+    let mut last_modified = (modified(&config.cert), modified(&config.key));
+    let mut interval = interval(RELOAD_INTERVAL);
+
+    loop {
+      interval.tick().await;
+
+      let modified = (modified(&config.cert), modified(&config.key));
+      if modified == last_modified {
+        continue
+      }
+
+      // Retry until both files have been replaced by a matching certificate and key.
+      match load(&config, &provider) {
+        Ok(key) => {
+          log::info!("Reloaded TLS certificate from {}.", config.cert.display());
+          *reloading.key.write().unwrap() = Arc::new(key);
+          last_modified = modified;
+          health.resolve("TLS");
+        },
+        Err(err) => health.report("TLS", err),
+      }
+    }
+  });
+
+  Ok(builder.with_cert_resolver(resolver))
+}